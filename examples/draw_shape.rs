@@ -42,11 +42,15 @@ fn draw_shape(r: &mut Raster, s: f32) {
             y: s * 139.0,
         },
     );
-    r.draw_quad(
+    r.draw_cubic(
         &Point {
             x: s * 50.0,
             y: s * 139.0,
         },
+        &Point {
+            x: s * 100.0,
+            y: s * 100.0,
+        },
         &Point {
             x: s * 100.0,
             y: s * 60.0,
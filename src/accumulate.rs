@@ -12,8 +12,11 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-#[cfg(feature = "sse")]
+#[cfg(feature = "portable-simd")]
+use std::simd::{f32x4, simd_swizzle, Simd, SimdFloat, SimdPartialOrd};
+
 use std::mem;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 #[cfg(all(feature = "sse", target_arch = "x86_64"))]
 use std::arch::x86_64::*;
@@ -68,8 +71,45 @@ pub fn accumulate(src: &[f32]) -> Vec<u8> {
     dst
 }
 
-#[cfg(not(feature = "sse"))]
-pub fn accumulate(src: &[f32]) -> Vec<u8> {
+/// Same running-prefix-sum-then-coverage-map algorithm as the `sse` path,
+/// but written against `std::simd` instead of raw x86 intrinsics, so
+/// aarch64 (NEON) and wasm32 (wasm128) targets get the same vectorization
+/// from one codebase rather than only x86_64.
+#[cfg(feature = "portable-simd")]
+pub fn accumulate_simd(src: &[f32]) -> Vec<u8> {
+    let len = src.len();
+    let n = (len + 3) & !3; // align data, same padding scheme as the sse path
+    let mut padded = vec![0.0f32; n];
+    padded[..len].copy_from_slice(src);
+    let mut dst: Vec<u8> = vec![0; n];
+
+    let zero = f32x4::splat(0.0);
+    let mut offset = zero;
+
+    for i in (0..n).step_by(4) {
+        let mut x = f32x4::from_slice(&padded[i..i + 4]);
+
+        // In-lane inclusive prefix sum: add x shifted up by one lane (zero
+        // fill), then by two lanes -- the `simd_swizzle!` calls reproduce
+        // the `slli_si128`/`shuffle_ps` steps of the sse path.
+        let shifted_by_1: f32x4 = simd_swizzle!(zero, x, [0, 4, 5, 6]);
+        x += shifted_by_1;
+        let shifted_by_2: f32x4 = simd_swizzle!(zero, x, [0, 1, 4, 5]);
+        x += shifted_by_2;
+        x += offset;
+
+        let y = x.abs().simd_min(f32x4::splat(1.0)) * f32x4::splat(255.0);
+        let bytes: Simd<u8, 4> = y.cast::<u32>().cast();
+        dst[i..i + 4].copy_from_slice(bytes.as_array());
+
+        offset = f32x4::splat(x[3]);
+    }
+
+    dst.truncate(len);
+    dst
+}
+
+fn accumulate_scalar(src: &[f32]) -> Vec<u8> {
     let mut acc = 0.0;
     src.iter()
         .map(|c| {
@@ -82,6 +122,53 @@ pub fn accumulate(src: &[f32]) -> Vec<u8> {
         .collect()
 }
 
+#[cfg(not(feature = "sse"))]
+pub fn accumulate(src: &[f32]) -> Vec<u8> {
+    accumulate_scalar(src)
+}
+
+type AccumulateFn = fn(&[f32]) -> Vec<u8>;
+
+// 0 means "not yet probed"; a real fn pointer is never null, so that value
+// can't collide with a cached choice.
+static DISPATCH: AtomicUsize = AtomicUsize::new(0);
+
+fn detect_impl() -> AccumulateFn {
+    #[cfg(all(feature = "portable-simd", target_arch = "x86_64"))]
+    {
+        if is_x86_feature_detected!("sse2") {
+            return accumulate_simd;
+        }
+    }
+    #[cfg(all(feature = "portable-simd", target_arch = "aarch64"))]
+    {
+        if std::arch::is_aarch64_feature_detected!("neon") {
+            return accumulate_simd;
+        }
+    }
+    accumulate_scalar
+}
+
+/// Picks the fastest `accumulate` implementation the *running* machine
+/// actually supports, probing once with `is_x86_feature_detected!`/
+/// `is_aarch64_feature_detected!` and caching the choice in `DISPATCH`
+/// rather than re-probing on every call. Falls back to the plain scalar
+/// loop when nothing matches (including builds without the
+/// `portable-simd` feature, where no vectorized candidate exists at all).
+/// Unlike the `sse`-feature path, the same binary adapts to whatever CPU
+/// it ends up running on instead of committing to one at build time.
+pub fn accumulate_auto(src: &[f32]) -> Vec<u8> {
+    let cached = DISPATCH.load(Ordering::Relaxed);
+    let f: AccumulateFn = if cached != 0 {
+        unsafe { mem::transmute(cached) }
+    } else {
+        let f = detect_impl();
+        DISPATCH.store(f as usize, Ordering::Relaxed);
+        f
+    };
+    f(src)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -101,6 +188,8 @@ mod tests {
     }
     fn test_accumulate(src: Vec<f32>) {
         assert_eq!(accumulate_simple_impl(&src), accumulate(&src));
+        #[cfg(feature = "portable-simd")]
+        assert_eq!(accumulate_simple_impl(&src), accumulate_simd(&src));
     }
 
     #[test]
@@ -183,4 +272,15 @@ mod tests {
     fn simple_7() {
         test_accumulate(vec![0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7]);
     }
+
+    #[test]
+    fn auto_matches_scalar_and_caches_dispatch() {
+        let src = vec![0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7];
+        let expected = accumulate_simple_impl(&src);
+        // First call probes and caches into `DISPATCH`; second call must hit
+        // the cached path. Both must agree with the reference impl.
+        assert_eq!(expected, accumulate_auto(&src));
+        assert_eq!(expected, accumulate_auto(&src));
+        assert_ne!(DISPATCH.load(Ordering::Relaxed), 0);
+    }
 }
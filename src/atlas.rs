@@ -0,0 +1,165 @@
+// Copyright 2023 Google Inc. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A shelf-packed glyph atlas: packs many rasterized glyph coverage bitmaps
+//! into one larger texture and hands back each glyph's rect, the way a GPU
+//! text renderer wants to upload a cache texture once and sample many
+//! glyphs out of it.
+
+/// A rectangle allocated within an `Atlas`, in atlas pixel coordinates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub w: usize,
+    pub h: usize,
+}
+
+struct Shelf {
+    y: usize,
+    height: usize,
+    x: usize,
+}
+
+/// A single-channel coverage texture built by shelf (row) packing. Each
+/// shelf is a horizontal strip as tall as the tallest glyph placed in it
+/// so far; `insert` prefers the existing shelf that wastes the least
+/// vertical space and still has horizontal room, falling back to opening a
+/// new shelf at the current bottom.
+pub struct Atlas {
+    width: usize,
+    height: usize,
+    data: Vec<u8>,
+    shelves: Vec<Shelf>,
+}
+
+impl Atlas {
+    pub fn new(width: usize, height: usize) -> Atlas {
+        Atlas {
+            width: width,
+            height: height,
+            data: vec![0; width * height],
+            shelves: Vec::new(),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Allocates a `w x h` rect. Returns `None` if `w` is wider than the
+    /// atlas, or no existing shelf fits and opening a new one would exceed
+    /// the atlas height -- the caller's cue to grow the atlas and re-insert.
+    pub fn insert(&mut self, w: usize, h: usize) -> Option<Rect> {
+        if w > self.width || h > self.height {
+            return None;
+        }
+        let mut best: Option<(usize, usize)> = None;
+        for (i, shelf) in self.shelves.iter().enumerate() {
+            if shelf.height >= h && shelf.x + w <= self.width {
+                let waste = shelf.height - h;
+                if best.map_or(true, |(_, best_waste)| waste < best_waste) {
+                    best = Some((i, waste));
+                }
+            }
+        }
+        if let Some((i, _)) = best {
+            let shelf = &mut self.shelves[i];
+            let rect = Rect { x: shelf.x, y: shelf.y, w: w, h: h };
+            shelf.x += w;
+            return Some(rect);
+        }
+        let y = self.shelves.last().map_or(0, |s| s.y + s.height);
+        if y + h > self.height {
+            return None;
+        }
+        self.shelves.push(Shelf { y: y, height: h, x: w });
+        Some(Rect { x: 0, y: y, w: w, h: h })
+    }
+
+    /// Copies a glyph's row-major coverage bytes (`rect.w * rect.h` of
+    /// them, as produced by `Raster::get_bitmap`) into the backing buffer
+    /// at the rect `insert` returned for it.
+    pub fn blit(&mut self, rect: &Rect, coverage: &[u8]) {
+        for row in 0..rect.h {
+            let src = &coverage[row * rect.w .. (row + 1) * rect.w];
+            let dst_start = (rect.y + row) * self.width + rect.x;
+            self.data[dst_start .. dst_start + rect.w].copy_from_slice(src);
+        }
+    }
+
+    /// The full backing buffer, `width() * height()` bytes, row-major.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insert_packs_shelves_left_to_right() {
+        let mut atlas = Atlas::new(10, 10);
+        let r1 = atlas.insert(4, 3).unwrap();
+        let r2 = atlas.insert(4, 3).unwrap();
+        assert_eq!(r1, Rect { x: 0, y: 0, w: 4, h: 3 });
+        assert_eq!(r2, Rect { x: 4, y: 0, w: 4, h: 3 });
+    }
+
+    #[test]
+    fn insert_opens_a_new_shelf_when_the_row_is_full() {
+        let mut atlas = Atlas::new(6, 10);
+        atlas.insert(4, 3).unwrap();
+        // Doesn't fit beside the first rect (4 + 4 > 6 width), so it should
+        // open a new shelf below the first one.
+        let r2 = atlas.insert(4, 3).unwrap();
+        assert_eq!(r2, Rect { x: 0, y: 3, w: 4, h: 3 });
+    }
+
+    #[test]
+    fn insert_prefers_the_least_wasteful_existing_shelf() {
+        let mut atlas = Atlas::new(20, 20);
+        atlas.insert(2, 3).unwrap(); // opens shelf0, height 3
+        atlas.insert(2, 10).unwrap(); // too tall for shelf0 -> opens shelf1, height 10
+        // h=3 fits both shelves (waste 0 in shelf0, waste 7 in shelf1); the
+        // least-wasteful one (shelf0) should win.
+        let r = atlas.insert(2, 3).unwrap();
+        assert_eq!(r, Rect { x: 2, y: 0, w: 2, h: 3 });
+    }
+
+    #[test]
+    fn insert_fails_when_nothing_fits() {
+        let mut atlas = Atlas::new(4, 4);
+        assert_eq!(atlas.insert(5, 1), None); // wider than the atlas
+        atlas.insert(4, 3).unwrap();
+        assert_eq!(atlas.insert(4, 2), None); // a new shelf would exceed height
+    }
+
+    #[test]
+    fn blit_copies_row_major_coverage_into_place() {
+        let mut atlas = Atlas::new(4, 4);
+        let rect = atlas.insert(2, 2).unwrap();
+        atlas.blit(&rect, &[1, 2, 3, 4]);
+        assert_eq!(atlas.data()[0], 1);
+        assert_eq!(atlas.data()[1], 2);
+        assert_eq!(atlas.data()[4], 3);
+        assert_eq!(atlas.data()[5], 4);
+        assert_eq!(atlas.data()[2], 0); // untouched background stays 0
+    }
+}
@@ -0,0 +1,111 @@
+// Copyright 2020 Google Inc. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A bounded cache of rasterized glyphs, keyed by glyph id, pixel size, and
+//! quantized subpixel offset, so terminals and other text-heavy UIs don't
+//! re-rasterize the same glyph on every frame.
+
+use std::collections::{HashMap, VecDeque};
+
+use font::{Font, GlyphBitmap};
+
+/// How finely the fractional pixel offset is quantized before being folded
+/// into the cache key; 4 steps means glyphs are cached at quarter-pixel
+/// subpixel positioning.
+const SUBPIXEL_STEPS: f32 = 4.0;
+
+fn quantize(offset: f32) -> u8 {
+    let frac = offset - offset.floor();
+    (frac * SUBPIXEL_STEPS) as u8
+}
+
+/// Identifies a single cached rasterization.
+#[derive(PartialEq, Eq, Hash, Clone, Copy)]
+pub struct GlyphKey {
+    pub glyph_id: u16,
+    pub size: u32,
+    pub subpixel_x: u8,
+    pub subpixel_y: u8,
+}
+
+impl GlyphKey {
+    pub fn new(glyph_id: u16, size: u32, x_offset: f32, y_offset: f32) -> GlyphKey {
+        GlyphKey {
+            glyph_id: glyph_id,
+            size: size,
+            subpixel_x: quantize(x_offset),
+            subpixel_y: quantize(y_offset),
+        }
+    }
+}
+
+/// A glyph rasterization cache bounded to `capacity` entries, evicting the
+/// least-recently-used glyph once that capacity is exceeded.
+pub struct GlyphCache {
+    capacity: usize,
+    entries: HashMap<GlyphKey, GlyphBitmap>,
+    recency: VecDeque<GlyphKey>,
+    hits: u64,
+    misses: u64,
+}
+
+impl GlyphCache {
+    pub fn new(capacity: usize) -> GlyphCache {
+        GlyphCache {
+            capacity: capacity,
+            entries: HashMap::new(),
+            recency: VecDeque::new(),
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    fn touch(&mut self, key: GlyphKey) {
+        self.recency.retain(|&k| k != key);
+        self.recency.push_back(key);
+    }
+
+    /// Returns the rasterized glyph for `key`, rasterizing via `font` and
+    /// inserting into the cache on a miss. Returns `None` if the glyph
+    /// can't be rasterized (unknown id, degenerate outline).
+    pub fn get_or_rasterize(&mut self, font: &Font, key: GlyphKey) -> Option<&GlyphBitmap> {
+        if self.entries.contains_key(&key) {
+            self.hits += 1;
+            self.touch(key);
+        } else {
+            self.misses += 1;
+            let bitmap = font.render_glyph(key.glyph_id, key.size)?;
+            if self.entries.len() >= self.capacity {
+                if let Some(oldest) = self.recency.pop_front() {
+                    self.entries.remove(&oldest);
+                }
+            }
+            self.entries.insert(key, bitmap);
+            self.touch(key);
+        }
+        self.entries.get(&key)
+    }
+}
@@ -0,0 +1,786 @@
+// Copyright 2021 Google Inc. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Minimal CFF / Type2 charstring support, enough to pull glyph outlines out
+//! of an OpenType-CFF (`OTTO`) font, which carries no `glyf`/`loca` table.
+
+use std::collections::HashMap;
+use std::mem;
+
+use geom::Point;
+
+// Bounds-checked reads, mirroring `font.rs`'s `get_u16`/`get_u32`: CFF table
+// bytes come straight from the font file, so a truncated or malformed INDEX,
+// DICT, or charstring must fail gracefully instead of indexing past the end
+// of the slice.
+fn get_u8(data: &[u8], off: usize) -> Option<u8> {
+    data.get(off).copied()
+}
+
+fn get_u16(data: &[u8], off: usize) -> Option<u16> {
+    if off + 2 > data.len() {
+        None
+    } else {
+        Some(((data[off] as u16) << 8) | data[off + 1] as u16)
+    }
+}
+
+/// A CFF INDEX: a count-prefixed table of variable-length byte strings.
+struct Index<'a> {
+    data: &'a [u8],
+    offsets: Vec<u32>,
+}
+
+impl<'a> Index<'a> {
+    fn parse(data: &'a [u8], start: usize) -> Option<(Index<'a>, usize)> {
+        let count = get_u16(data, start)? as usize;
+        if count == 0 {
+            return Some((Index { data: &data[start + 2..start + 2], offsets: vec![] }, start + 2));
+        }
+        let off_size = get_u8(data, start + 2)? as usize;
+        if off_size == 0 || off_size > 4 {
+            return None;
+        }
+        let offsets_start = start + 3;
+        let mut offsets = Vec::with_capacity(count + 1);
+        for i in 0..=count {
+            let base = offsets_start.checked_add(i.checked_mul(off_size)?)?;
+            let mut v = 0u32;
+            for b in 0..off_size {
+                v = (v << 8) | get_u8(data, base + b)? as u32;
+            }
+            offsets.push(v);
+        }
+        let data_start = offsets_start.checked_add((count + 1).checked_mul(off_size)?)?.checked_sub(1)?;
+        let end = data_start.checked_add(*offsets.last()? as usize)?;
+        if end > data.len() || data_start > end {
+            return None;
+        }
+        Some((Index { data: &data[data_start..end], offsets: offsets }, end))
+    }
+
+    fn len(&self) -> usize {
+        if self.offsets.is_empty() { 0 } else { self.offsets.len() - 1 }
+    }
+
+    fn get(&self, i: usize) -> Option<&'a [u8]> {
+        let start = (*self.offsets.get(i)?).checked_sub(1)? as usize;
+        let end = (*self.offsets.get(i + 1)?).checked_sub(1)? as usize;
+        if start > end || end > self.data.len() {
+            return None;
+        }
+        Some(&self.data[start..end])
+    }
+}
+
+/// Parses a CFF DICT (Top DICT or Private DICT) into a map from operator
+/// code to its operand list. Two-byte operators (`12 n`) are folded into
+/// `1200 + n` so both fit in one `u16` key space.
+///
+/// Truncated data (a multi-byte operand or operator cut off mid-way) simply
+/// stops the parse and returns whatever entries were decoded so far, rather
+/// than panicking on an out-of-bounds index.
+fn parse_dict(data: &[u8]) -> HashMap<u16, Vec<f64>> {
+    let mut dict = HashMap::new();
+    let mut operands = Vec::new();
+    let mut i = 0;
+    while i < data.len() {
+        let b0 = data[i];
+        if b0 <= 21 {
+            let op = if b0 == 12 {
+                i += 1;
+                let b1 = match get_u8(data, i) {
+                    Some(b) => b,
+                    None => break,
+                };
+                1200 + b1 as u16
+            } else {
+                b0 as u16
+            };
+            dict.insert(op, operands.clone());
+            operands.clear();
+            i += 1;
+        } else if b0 == 28 {
+            let (b1, b2) = match (get_u8(data, i + 1), get_u8(data, i + 2)) {
+                (Some(b1), Some(b2)) => (b1, b2),
+                _ => break,
+            };
+            let v = (((b1 as i16) << 8) | b2 as i16) as f64;
+            operands.push(v);
+            i += 3;
+        } else if b0 == 29 {
+            let bytes = match (get_u8(data, i + 1), get_u8(data, i + 2), get_u8(data, i + 3), get_u8(data, i + 4)) {
+                (Some(b1), Some(b2), Some(b3), Some(b4)) => (b1, b2, b3, b4),
+                _ => break,
+            };
+            let v = (((bytes.0 as u32) << 24)
+                | ((bytes.1 as u32) << 16)
+                | ((bytes.2 as u32) << 8)
+                | bytes.3 as u32) as i32 as f64;
+            operands.push(v);
+            i += 5;
+        } else if b0 == 30 {
+            i += 1;
+            let mut s = String::new();
+            'nibbles: loop {
+                let byte = match get_u8(data, i) {
+                    Some(b) => b,
+                    None => break 'nibbles,
+                };
+                i += 1;
+                for &nibble in &[byte >> 4, byte & 0xf] {
+                    match nibble {
+                        0..=9 => s.push((b'0' + nibble) as char),
+                        0xa => s.push('.'),
+                        0xb => s.push('E'),
+                        0xc => s.push_str("E-"),
+                        0xe => s.push('-'),
+                        0xf => break 'nibbles,
+                        _ => {}
+                    }
+                }
+            }
+            operands.push(s.parse().unwrap_or(0.0));
+        } else if b0 >= 32 && b0 <= 246 {
+            operands.push(b0 as f64 - 139.0);
+            i += 1;
+        } else if b0 >= 247 && b0 <= 250 {
+            let b1 = match get_u8(data, i + 1) {
+                Some(b) => b,
+                None => break,
+            };
+            operands.push((b0 as f64 - 247.0) * 256.0 + b1 as f64 + 108.0);
+            i += 2;
+        } else if b0 >= 251 && b0 <= 254 {
+            let b1 = match get_u8(data, i + 1) {
+                Some(b) => b,
+                None => break,
+            };
+            operands.push(-(b0 as f64 - 251.0) * 256.0 - b1 as f64 - 108.0);
+            i += 2;
+        } else {
+            i += 1;
+        }
+    }
+    dict
+}
+
+const OP_CHARSTRINGS: u16 = 17;
+const OP_PRIVATE: u16 = 18;
+const OP_SUBRS: u16 = 19;
+
+/// One command in a CFF glyph's (unflattened) outline. Cubic curves are kept
+/// as true cubics rather than flattened here; `Raster::draw_cubic` does the
+/// flattening at rasterization time, same as `draw_quad` does for `glyf`'s
+/// quadratics.
+#[derive(Debug, Clone, Copy)]
+pub enum PathOp {
+    MoveTo(Point),
+    LineTo(Point),
+    CurveTo(Point, Point, Point),
+}
+
+fn subr_bias(count: usize) -> i32 {
+    if count < 1240 {
+        107
+    } else if count < 33900 {
+        1131
+    } else {
+        32768
+    }
+}
+
+/// A parsed `CFF ` table: just the pieces needed to walk a glyph's Type2
+/// charstring and its local/global subroutines.
+pub struct Cff<'a> {
+    char_strings: Index<'a>,
+    global_subrs: Index<'a>,
+    local_subrs: Index<'a>,
+}
+
+impl<'a> Cff<'a> {
+    pub fn parse(data: &'a [u8]) -> Option<Cff<'a>> {
+        if data.len() < 4 {
+            return None;
+        }
+        let hdr_size = data[2] as usize;
+        let (_name_index, pos) = Index::parse(data, hdr_size)?;
+        let (top_dict_index, pos) = Index::parse(data, pos)?;
+        let (_string_index, pos) = Index::parse(data, pos)?;
+        let (global_subrs, _) = Index::parse(data, pos)?;
+        let top_dict = parse_dict(top_dict_index.get(0)?);
+        let charstrings_off = *top_dict.get(&OP_CHARSTRINGS)?.get(0)? as usize;
+        let (char_strings, _) = Index::parse(data, charstrings_off)?;
+
+        let local_subrs = top_dict
+            .get(&OP_PRIVATE)
+            .and_then(|private| {
+                if private.len() != 2 {
+                    return None;
+                }
+                let priv_size = private[0] as usize;
+                let priv_off = private[1] as usize;
+                let priv_end = priv_off.checked_add(priv_size)?;
+                if priv_end > data.len() {
+                    return None;
+                }
+                let priv_dict = parse_dict(&data[priv_off..priv_end]);
+                let subrs_rel = *priv_dict.get(&OP_SUBRS)?.get(0)? as usize;
+                let subrs_off = priv_off.checked_add(subrs_rel)?;
+                Index::parse(data, subrs_off).map(|(idx, _)| idx)
+            })
+            .unwrap_or_else(|| Index { data: &data[0..0], offsets: vec![] });
+
+        Some(Cff {
+            char_strings: char_strings,
+            global_subrs: global_subrs,
+            local_subrs: local_subrs,
+        })
+    }
+
+    pub fn num_glyphs(&self) -> u16 {
+        self.char_strings.len() as u16
+    }
+
+    /// Executes a glyph's Type2 charstring, returning its outline as a list
+    /// of contours, each an ordered `MoveTo`/`LineTo`/`CurveTo` command
+    /// stream (cubics are kept as true cubics; see `PathOp`).
+    pub fn outline(&self, glyph_id: u16) -> Option<Vec<Vec<PathOp>>> {
+        let code = self.char_strings.get(glyph_id as usize)?;
+        let mut interp = Interp::new(&self.global_subrs, &self.local_subrs);
+        interp.run(code, 0);
+        interp.finish();
+        Some(interp.contours)
+    }
+}
+
+struct Interp<'a> {
+    global_subrs: &'a Index<'a>,
+    local_subrs: &'a Index<'a>,
+    global_bias: i32,
+    local_bias: i32,
+    stack: Vec<f32>,
+    x: f32,
+    y: f32,
+    n_stems: usize,
+    have_width: bool,
+    contours: Vec<Vec<PathOp>>,
+    current: Vec<PathOp>,
+}
+
+// Type2 charstrings can recurse through subroutines; cap the depth so a
+// malformed or cyclic font can't blow the stack.
+const MAX_SUBR_DEPTH: usize = 10;
+
+impl<'a> Interp<'a> {
+    fn new(global_subrs: &'a Index<'a>, local_subrs: &'a Index<'a>) -> Interp<'a> {
+        Interp {
+            global_subrs: global_subrs,
+            local_subrs: local_subrs,
+            global_bias: subr_bias(global_subrs.len()),
+            local_bias: subr_bias(local_subrs.len()),
+            stack: Vec::new(),
+            x: 0.0,
+            y: 0.0,
+            n_stems: 0,
+            have_width: false,
+            contours: Vec::new(),
+            current: Vec::new(),
+        }
+    }
+
+    // The first stem/move/endchar operator may carry a leading glyph-width
+    // argument ahead of its normal operands; detect that by the operand
+    // count exceeding what the operator needs and drop it, since widths
+    // aren't needed to rasterize an outline.
+    fn maybe_take_width(&mut self, expected: usize) {
+        if !self.have_width {
+            if self.stack.len() > expected {
+                self.stack.remove(0);
+            }
+            self.have_width = true;
+        }
+    }
+
+    fn moveto(&mut self, dx: f32, dy: f32) {
+        if !self.current.is_empty() {
+            let done = mem::replace(&mut self.current, Vec::new());
+            self.contours.push(done);
+        }
+        self.x += dx;
+        self.y += dy;
+        self.current.push(PathOp::MoveTo(Point::new(self.x, self.y)));
+    }
+
+    fn lineto(&mut self, dx: f32, dy: f32) {
+        self.x += dx;
+        self.y += dy;
+        self.current.push(PathOp::LineTo(Point::new(self.x, self.y)));
+    }
+
+    fn curveto(&mut self, dx1: f32, dy1: f32, dx2: f32, dy2: f32, dx3: f32, dy3: f32) {
+        let p1 = Point::new(self.x + dx1, self.y + dy1);
+        let p2 = Point::new(p1.x + dx2, p1.y + dy2);
+        let p3 = Point::new(p2.x + dx3, p2.y + dy3);
+        self.current.push(PathOp::CurveTo(p1, p2, p3));
+        self.x = p3.x;
+        self.y = p3.y;
+    }
+
+    fn finish(&mut self) {
+        if !self.current.is_empty() {
+            let done = mem::replace(&mut self.current, Vec::new());
+            self.contours.push(done);
+        }
+    }
+
+    // hvcurveto/vhcurveto: a run of curves with alternating start/end
+    // tangents, where the very last curve may carry one extra trailing
+    // operand for its otherwise-implicit-zero axis.
+    fn alternating_curves(&mut self, args: &[f32], mut horizontal: bool) {
+        let mut i = 0;
+        while i + 4 <= args.len() {
+            let df = if args.len() - i == 5 { args[i + 4] } else { 0.0 };
+            if horizontal {
+                self.curveto(args[i], 0.0, args[i + 1], args[i + 2], df, args[i + 3]);
+            } else {
+                self.curveto(0.0, args[i], args[i + 1], args[i + 2], args[i + 3], df);
+            }
+            horizontal = !horizontal;
+            i += 4;
+        }
+    }
+
+    fn run(&mut self, code: &[u8], depth: usize) {
+        if depth > MAX_SUBR_DEPTH {
+            return;
+        }
+        let mut i = 0;
+        while i < code.len() {
+            let b0 = code[i];
+            if b0 >= 32 || b0 == 28 {
+                if b0 == 28 {
+                    let (b1, b2) = match (code.get(i + 1), code.get(i + 2)) {
+                        (Some(&b1), Some(&b2)) => (b1, b2),
+                        _ => break,
+                    };
+                    let v = (((b1 as i16) << 8) | b2 as i16) as f32;
+                    self.stack.push(v);
+                    i += 3;
+                } else if b0 < 247 {
+                    self.stack.push(b0 as f32 - 139.0);
+                    i += 1;
+                } else if b0 < 251 {
+                    let b1 = match code.get(i + 1) {
+                        Some(&b) => b,
+                        None => break,
+                    };
+                    self.stack.push((b0 as f32 - 247.0) * 256.0 + b1 as f32 + 108.0);
+                    i += 2;
+                } else if b0 < 255 {
+                    let b1 = match code.get(i + 1) {
+                        Some(&b) => b,
+                        None => break,
+                    };
+                    self.stack.push(-(b0 as f32 - 251.0) * 256.0 - b1 as f32 - 108.0);
+                    i += 2;
+                } else {
+                    let bytes = match (code.get(i + 1), code.get(i + 2), code.get(i + 3), code.get(i + 4)) {
+                        (Some(&b1), Some(&b2), Some(&b3), Some(&b4)) => (b1, b2, b3, b4),
+                        _ => break,
+                    };
+                    let v = (((bytes.0 as u32) << 24)
+                        | ((bytes.1 as u32) << 16)
+                        | ((bytes.2 as u32) << 8)
+                        | bytes.3 as u32) as i32;
+                    self.stack.push(v as f32 / 65536.0);
+                    i += 5;
+                }
+                continue;
+            }
+            i += 1;
+            match b0 {
+                1 | 3 | 18 | 23 => {
+                    // hstem(hm)/vstem(hm): operands come in (y, dy) pairs.
+                    if !self.have_width && self.stack.len() % 2 == 1 {
+                        self.stack.remove(0);
+                    }
+                    self.have_width = true;
+                    self.n_stems += self.stack.len() / 2;
+                    self.stack.clear();
+                }
+                19 | 20 => {
+                    // hintmask/cntrmask: any operands still on the stack are
+                    // implicit trailing vstem hints, then skip the mask.
+                    if !self.have_width && self.stack.len() % 2 == 1 {
+                        self.stack.remove(0);
+                    }
+                    self.have_width = true;
+                    self.n_stems += self.stack.len() / 2;
+                    self.stack.clear();
+                    i += (self.n_stems + 7) / 8;
+                }
+                21 => {
+                    self.maybe_take_width(2);
+                    let dy = self.stack.pop().unwrap_or(0.0);
+                    let dx = self.stack.pop().unwrap_or(0.0);
+                    self.moveto(dx, dy);
+                    self.stack.clear();
+                }
+                22 => {
+                    self.maybe_take_width(1);
+                    let dx = self.stack.pop().unwrap_or(0.0);
+                    self.moveto(dx, 0.0);
+                    self.stack.clear();
+                }
+                4 => {
+                    self.maybe_take_width(1);
+                    let dy = self.stack.pop().unwrap_or(0.0);
+                    self.moveto(0.0, dy);
+                    self.stack.clear();
+                }
+                5 => {
+                    let args = mem::replace(&mut self.stack, Vec::new());
+                    for pair in args.chunks(2) {
+                        if pair.len() == 2 {
+                            self.lineto(pair[0], pair[1]);
+                        }
+                    }
+                }
+                6 | 7 => {
+                    let args = mem::replace(&mut self.stack, Vec::new());
+                    let mut horizontal = b0 == 6;
+                    for &v in &args {
+                        if horizontal {
+                            self.lineto(v, 0.0);
+                        } else {
+                            self.lineto(0.0, v);
+                        }
+                        horizontal = !horizontal;
+                    }
+                }
+                8 => {
+                    let args = mem::replace(&mut self.stack, Vec::new());
+                    for six in args.chunks(6) {
+                        if six.len() == 6 {
+                            self.curveto(six[0], six[1], six[2], six[3], six[4], six[5]);
+                        }
+                    }
+                }
+                24 => {
+                    // rcurveline: curves, then a final line.
+                    let args = mem::replace(&mut self.stack, Vec::new());
+                    let n_curves = (args.len().saturating_sub(2)) / 6;
+                    for k in 0..n_curves {
+                        let six = &args[k * 6..k * 6 + 6];
+                        self.curveto(six[0], six[1], six[2], six[3], six[4], six[5]);
+                    }
+                    let tail = &args[n_curves * 6..];
+                    if tail.len() == 2 {
+                        self.lineto(tail[0], tail[1]);
+                    }
+                }
+                25 => {
+                    // rlinecurve: lines, then a final curve.
+                    let args = mem::replace(&mut self.stack, Vec::new());
+                    let n_lines = (args.len().saturating_sub(6)) / 2;
+                    for k in 0..n_lines {
+                        self.lineto(args[k * 2], args[k * 2 + 1]);
+                    }
+                    let tail = &args[n_lines * 2..];
+                    if tail.len() == 6 {
+                        self.curveto(tail[0], tail[1], tail[2], tail[3], tail[4], tail[5]);
+                    }
+                }
+                26 => {
+                    // vvcurveto: optional leading dx1, then (dya dxb dyb dyc)+
+                    let args = mem::replace(&mut self.stack, Vec::new());
+                    let (dx1, rest) = if args.len() % 4 == 1 {
+                        (args[0], &args[1..])
+                    } else {
+                        (0.0, &args[..])
+                    };
+                    let mut first_dx = dx1;
+                    for four in rest.chunks(4) {
+                        if four.len() == 4 {
+                            self.curveto(first_dx, four[0], four[1], four[2], 0.0, four[3]);
+                            first_dx = 0.0;
+                        }
+                    }
+                }
+                27 => {
+                    // hhcurveto: optional leading dy1, then (dxa dxb dyb dxc)+
+                    let args = mem::replace(&mut self.stack, Vec::new());
+                    let (dy1, rest) = if args.len() % 4 == 1 {
+                        (args[0], &args[1..])
+                    } else {
+                        (0.0, &args[..])
+                    };
+                    let mut first_dy = dy1;
+                    for four in rest.chunks(4) {
+                        if four.len() == 4 {
+                            self.curveto(four[0], first_dy, four[1], four[2], four[3], 0.0);
+                            first_dy = 0.0;
+                        }
+                    }
+                }
+                30 => {
+                    let args = mem::replace(&mut self.stack, Vec::new());
+                    self.alternating_curves(&args, false);
+                }
+                31 => {
+                    let args = mem::replace(&mut self.stack, Vec::new());
+                    self.alternating_curves(&args, true);
+                }
+                10 => {
+                    if let Some(idx) = self.stack.pop() {
+                        let n = idx as i32 + self.local_bias;
+                        if n >= 0 {
+                            if let Some(code) = self.local_subrs.get(n as usize) {
+                                self.run(code, depth + 1);
+                            }
+                        }
+                    }
+                }
+                29 => {
+                    if let Some(idx) = self.stack.pop() {
+                        let n = idx as i32 + self.global_bias;
+                        if n >= 0 {
+                            if let Some(code) = self.global_subrs.get(n as usize) {
+                                self.run(code, depth + 1);
+                            }
+                        }
+                    }
+                }
+                11 => return,
+                14 => {
+                    self.maybe_take_width(0);
+                    return;
+                }
+                12 => {
+                    // Two-byte operators: the four flex variants draw real
+                    // path geometry (two chained curves each), so they need
+                    // to run through curveto like every other curve op.
+                    // Everything else in this range (arithmetic/logic ops)
+                    // isn't needed to rasterize an outline; just drop its
+                    // operands.
+                    if i >= code.len() {
+                        break;
+                    }
+                    let selector = code[i];
+                    i += 1;
+                    match selector {
+                        34 => {
+                            // hflex: dx1 dx2 dy2 dx3 dx4 dx5 dx6
+                            let args = mem::replace(&mut self.stack, Vec::new());
+                            if args.len() == 7 {
+                                self.curveto(args[0], 0.0, args[1], args[2], args[3], 0.0);
+                                self.curveto(args[4], 0.0, args[5], -args[2], args[6], 0.0);
+                            }
+                        }
+                        35 => {
+                            // flex: dx1 dy1 dx2 dy2 dx3 dy3 dx4 dy4 dx5 dy5
+                            //       dx6 dy6 fd (fd is a flex-depth hint, unused)
+                            let args = mem::replace(&mut self.stack, Vec::new());
+                            if args.len() == 13 {
+                                self.curveto(args[0], args[1], args[2], args[3], args[4], args[5]);
+                                self.curveto(args[6], args[7], args[8], args[9], args[10], args[11]);
+                            }
+                        }
+                        36 => {
+                            // hflex1: dx1 dy1 dx2 dy2 dx3 dx4 dx5 dy5 dx6
+                            let args = mem::replace(&mut self.stack, Vec::new());
+                            if args.len() == 9 {
+                                let dy6 = -(args[1] + args[3] + args[7]);
+                                self.curveto(args[0], args[1], args[2], args[3], args[4], 0.0);
+                                self.curveto(args[5], 0.0, args[6], args[7], args[8], dy6);
+                            }
+                        }
+                        37 => {
+                            // flex1: dx1 dy1 dx2 dy2 dx3 dy3 dx4 dy4 dx5 dy5 d6,
+                            // where d6 is dx6 or dy6 depending on whichever
+                            // axis accumulated the larger displacement.
+                            let args = mem::replace(&mut self.stack, Vec::new());
+                            if args.len() == 11 {
+                                let dx = args[0] + args[2] + args[4] + args[6] + args[8];
+                                let dy = args[1] + args[3] + args[5] + args[7] + args[9];
+                                let (dx6, dy6) = if dx.abs() > dy.abs() {
+                                    (args[10], -dy)
+                                } else {
+                                    (-dx, args[10])
+                                };
+                                self.curveto(args[0], args[1], args[2], args[3], args[4], args[5]);
+                                self.curveto(args[6], args[7], args[8], args[9], dx6, dy6);
+                            }
+                        }
+                        _ => {
+                            self.stack.clear();
+                        }
+                    }
+                }
+                _ => {
+                    self.stack.clear();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode_num(v: i32) -> u8 {
+        // Single-byte charstring/dict operand encoding, valid for -107..=107.
+        (v + 139) as u8
+    }
+
+    fn empty_index() -> Index<'static> {
+        Index { data: &[], offsets: vec![] }
+    }
+
+    #[test]
+    fn parse_dict_two_byte_operand() {
+        // 391, encoded per the 247..250 two-byte operand range, followed by
+        // operator 17 (CharStrings offset).
+        let data = [248, 27, 17];
+        let dict = parse_dict(&data);
+        assert_eq!(dict.get(&17), Some(&vec![391.0]));
+    }
+
+    #[test]
+    fn parse_dict_two_byte_operator() {
+        // A single-byte operand followed by the two-byte operator `12 6`,
+        // which should fold into key 1200 + 6 = 1206.
+        let data = [encode_num(100), 12, 6];
+        let dict = parse_dict(&data);
+        assert_eq!(dict.get(&1206), Some(&vec![100.0]));
+    }
+
+    #[test]
+    fn interp_moveto_lineto() {
+        let global = empty_index();
+        let local = empty_index();
+        let mut interp = Interp::new(&global, &local);
+        // rmoveto dx=10 dy=20, rlineto dx=5 dy=-5, endchar.
+        let code = [
+            encode_num(10), encode_num(20), 21,
+            encode_num(5), encode_num(-5), 5,
+            14,
+        ];
+        interp.run(&code, 0);
+        interp.finish();
+        assert_eq!(interp.contours.len(), 1);
+        match interp.contours[0][0] {
+            PathOp::MoveTo(p) => assert_eq!((p.x, p.y), (10.0, 20.0)),
+            _ => panic!("expected MoveTo"),
+        }
+        match interp.contours[0][1] {
+            PathOp::LineTo(p) => assert_eq!((p.x, p.y), (15.0, 15.0)),
+            _ => panic!("expected LineTo"),
+        }
+    }
+
+    #[test]
+    fn interp_hflex_draws_two_curves_and_returns_to_start_y() {
+        let global = empty_index();
+        let local = empty_index();
+        let mut interp = Interp::new(&global, &local);
+        // hflex: dx1 dx2 dy2 dx3 dx4 dx5 dx6, all 10 -- the second curve's
+        // -dy2 should cancel the first curve's dy2, landing back on y = 0.
+        let mut code: Vec<u8> = (0..7).map(|_| encode_num(10)).collect();
+        code.push(12);
+        code.push(34);
+        interp.run(&code, 0);
+        interp.finish();
+        assert_eq!(interp.contours.len(), 1);
+        assert_eq!(interp.contours[0].len(), 2);
+        for op in &interp.contours[0] {
+            match op {
+                PathOp::CurveTo(..) => {}
+                _ => panic!("expected CurveTo"),
+            }
+        }
+        assert_eq!(interp.x, 60.0);
+        assert_eq!(interp.y, 0.0);
+    }
+
+    #[test]
+    fn parse_dict_truncated_multibyte_operand_does_not_panic() {
+        // A 28-style two-byte operand whose second byte is missing.
+        let data = [28, 0x12];
+        assert_eq!(parse_dict(&data), HashMap::new());
+        // A 247..250-style two-byte operand with no following byte.
+        let data = [248];
+        assert_eq!(parse_dict(&data), HashMap::new());
+    }
+
+    #[test]
+    fn index_parse_truncated_count_returns_none() {
+        // Declares a count but is cut off before the offset array.
+        let data = [0, 5];
+        assert!(Index::parse(&data, 0).is_none());
+    }
+
+    #[test]
+    fn index_parse_count_overrunning_data_returns_none() {
+        // count=1, off_size=1, offsets [1, 200] -- 199 bytes of string data
+        // that the 5-byte buffer doesn't actually contain.
+        let data = [0, 1, 1, 1, 200];
+        assert!(Index::parse(&data, 0).is_none());
+    }
+
+    #[test]
+    fn interp_run_truncated_multibyte_number_does_not_panic() {
+        let global = empty_index();
+        let local = empty_index();
+        let mut interp = Interp::new(&global, &local);
+        // A 255-style (Fixed 16.16) operand cut off after one byte.
+        let code = [255u8, 0];
+        interp.run(&code, 0);
+        interp.finish();
+        assert!(interp.contours.is_empty());
+    }
+
+    #[test]
+    fn interp_vvcurveto_bad_operand_count_does_not_panic() {
+        let global = empty_index();
+        let local = empty_index();
+        let mut interp = Interp::new(&global, &local);
+        // vvcurveto (26) with 2 args on the stack: not `4n` or `4n+1`, so the
+        // final chunk is short and must be skipped rather than indexed into.
+        let code = [encode_num(10), encode_num(10), 26, 14];
+        interp.run(&code, 0);
+        interp.finish();
+        assert!(interp.contours.is_empty());
+    }
+
+    #[test]
+    fn interp_hhcurveto_bad_operand_count_does_not_panic() {
+        let global = empty_index();
+        let local = empty_index();
+        let mut interp = Interp::new(&global, &local);
+        // hhcurveto (27) with 3 args on the stack: not `4n` or `4n+1`.
+        let code = [encode_num(10), encode_num(10), encode_num(10), 27, 14];
+        interp.run(&code, 0);
+        interp.finish();
+        assert!(interp.contours.is_empty());
+    }
+
+    #[test]
+    fn cff_parse_on_truncated_data_does_not_panic() {
+        assert!(Cff::parse(&[0, 1, 4, 0]).is_none());
+        assert!(Cff::parse(&[]).is_none());
+    }
+}
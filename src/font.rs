@@ -14,13 +14,17 @@
 
 //! A simple renderer for TrueType fonts
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fmt;
 use std::fmt::{Debug, Display, Formatter};
 use std::result::Result;
 
+use atlas::Atlas;
+use cff::{self, Cff};
 use geom::{affine_pt, Affine, Point};
-use raster::Raster;
+use kerning::{self, Gpos, Kern};
+use raster::{Raster, RasterError};
+use variations::{self, Avar, Fvar, Gvar};
 
 #[derive(PartialEq, Eq, Hash)]
 struct Tag(u32);
@@ -60,6 +64,34 @@ fn get_f2_14(data: &[u8], off: usize) -> Option<f32> {
     get_i16(data, off).map(|x| x as f32 * (1.0 / (1 << 14) as f32))
 }
 
+fn put_u16(out: &mut Vec<u8>, v: u16) {
+    out.push((v >> 8) as u8);
+    out.push(v as u8);
+}
+
+fn put_i16(out: &mut Vec<u8>, v: i16) {
+    put_u16(out, v as u16);
+}
+
+fn put_u32(out: &mut Vec<u8>, v: u32) {
+    out.push((v >> 24) as u8);
+    out.push((v >> 16) as u8);
+    out.push((v >> 8) as u8);
+    out.push(v as u8);
+}
+
+/// sfnt table checksum: the sum of the table's data as big-endian `u32`
+/// words, treating any trailing partial word as zero-padded.
+fn table_checksum(data: &[u8]) -> u32 {
+    let mut sum: u32 = 0;
+    for chunk in data.chunks(4) {
+        let mut word = [0u8; 4];
+        word[..chunk.len()].copy_from_slice(chunk);
+        sum = sum.wrapping_add(get_u32(&word, 0).unwrap());
+    }
+    sum
+}
+
 fn get_u32(data: &[u8], off: usize) -> Option<u32> {
     if off + 3 > data.len() {
         None
@@ -146,6 +178,30 @@ impl<'a> Hmtx<'a> {
     }
 }
 
+struct Post<'a>(&'a [u8]);
+
+impl<'a> Post<'a> {
+    fn underline_position(&self) -> Option<i16> {
+        get_i16(self.0, 8)
+    }
+
+    fn underline_thickness(&self) -> Option<i16> {
+        get_i16(self.0, 10)
+    }
+}
+
+struct Os2<'a>(&'a [u8]);
+
+impl<'a> Os2<'a> {
+    fn strikeout_size(&self) -> Option<i16> {
+        get_i16(self.0, 26)
+    }
+
+    fn strikeout_position(&self) -> Option<i16> {
+        get_i16(self.0, 28)
+    }
+}
+
 struct EncodingRecord<'a>(&'a [u8]);
 
 impl<'a> EncodingRecord<'a> {
@@ -340,6 +396,75 @@ impl<'a> EncodingFormat4<'a> {
     }
 }
 
+/// cmap subtable format 12: segmented coverage. Unlike format 4, the groups
+/// are full `u32` code points, so this is what lets a font map supplementary
+/// plane characters (emoji, CJK extensions) to glyph ids.
+struct EncodingFormat12<'a>(&'a [u8]);
+
+impl<'a> EncodingFormat12<'a> {
+    fn get_num_groups(&self) -> u32 {
+        get_u32(self.0, 12).unwrap()
+    }
+
+    fn group_start_char_code(&self, i: u32) -> u32 {
+        get_u32(self.0, 16 + i as usize * 12).unwrap()
+    }
+
+    fn group_end_char_code(&self, i: u32) -> u32 {
+        get_u32(self.0, 16 + i as usize * 12 + 4).unwrap()
+    }
+
+    fn group_start_glyph_id(&self, i: u32) -> u32 {
+        get_u32(self.0, 16 + i as usize * 12 + 8).unwrap()
+    }
+
+    pub fn lookup_glyph_id(&self, code_point: u32) -> Option<u16> {
+        let mut start = 0;
+        let mut end = self.get_num_groups();
+        while end > start {
+            let index = start + (end - start) / 2;
+            let start_char_code = self.group_start_char_code(index);
+            let end_char_code = self.group_end_char_code(index);
+            if code_point < start_char_code {
+                end = index;
+            } else if code_point > end_char_code {
+                start = index + 1;
+            } else {
+                let start_glyph_id = self.group_start_glyph_id(index);
+                return Some((start_glyph_id + (code_point - start_char_code)) as u16);
+            }
+        }
+        None
+    }
+}
+
+/// cmap subtable format 6: trimmed table mapping, a simple array of glyph
+/// ids covering one contiguous run of code points starting at `firstCode`.
+struct EncodingFormat6<'a>(&'a [u8]);
+
+impl<'a> EncodingFormat6<'a> {
+    fn get_first_code(&self) -> u16 {
+        get_u16(self.0, 6).unwrap()
+    }
+
+    fn get_entry_count(&self) -> u16 {
+        get_u16(self.0, 8).unwrap()
+    }
+
+    pub fn lookup_glyph_id(&self, code_point: u16) -> Option<u16> {
+        let first_code = self.get_first_code();
+        let entry_count = self.get_entry_count();
+        if code_point < first_code || code_point - first_code >= entry_count {
+            return None;
+        }
+        let index = (code_point - first_code) as usize;
+        match get_u16(self.0, 10 + index * 2) {
+            Some(0) => None,
+            id => id,
+        }
+    }
+}
+
 impl<'a> Debug for EncodingFormat4<'a> {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         f.debug_struct("EncodingFormat4")
@@ -373,60 +498,92 @@ impl<'a> Cmap<'a> {
         if index >= self.get_num_tables() {
             return None;
         }
-        let enc_offset = (index * 8 + 4) as usize;
-        let encoding_data = &self.0[enc_offset as usize..(enc_offset + 12) as usize];
+        let enc_offset = (index as usize) * 8 + 4;
+        let encoding_data = self.0.get(enc_offset..enc_offset + 12)?;
         Some(EncodingRecord(encoding_data))
     }
 
     fn get_encoding_records(&self) -> Vec<EncodingRecord> {
-        let mut encodings = vec![];
-        for i in 0..self.get_num_tables() {
-            encodings.push(self.get_encoding_record(i).unwrap());
-        }
-        encodings
+        (0..self.get_num_tables()).filter_map(|i| self.get_encoding_record(i)).collect()
+    }
+
+    /// Slices out the subtable at `offset`, bounds-checked against `self.0`
+    /// and against the subtable's own declared length -- which lives in a
+    /// `u16` right after the format for every format this module supports
+    /// except 12, whose header is `format:u16, reserved:u16, length:u32`.
+    fn subtable_at(&self, offset: u32) -> Option<&'a [u8]> {
+        let start = offset as usize;
+        let format = get_u16(self.0, start)?;
+        let subtable_len = if format == 12 {
+            get_u32(self.0, start + 4)?
+        } else {
+            get_u16(self.0, start + 2)? as u32
+        } as usize;
+        let end = start.checked_add(subtable_len)?;
+        self.0.get(start..end)
     }
 
     fn get_encoding(&self, index: u16) -> Option<Encoding<'a>> {
-        if index >= self.get_num_tables() {
+        let record = self.get_encoding_record(index)?;
+        Some(Encoding(self.subtable_at(record.get_offset())?))
+    }
+
+    fn get_encoding_format_4_at(&self, index: u16) -> Option<EncodingFormat4<'a>> {
+        let encoding = self.get_encoding(index)?;
+        if encoding.get_format() != 4 {
             return None;
         }
-        let record = self.get_encoding_record(index).unwrap();
-        let subtable_len = get_u16(self.0, (record.get_offset() + 2) as usize).unwrap() as u32;
-        let encoding_data =
-            &self.0[record.get_offset() as usize..(record.get_offset() + subtable_len) as usize];
-        Some(Encoding(encoding_data))
+        Some(EncodingFormat4(encoding.0))
     }
 
-    fn get_encoding_format_4_at(&self, index: u16) -> Option<EncodingFormat4<'a>> {
-        let encoding = self.get_encoding(index);
-        if encoding.is_none() || encoding.unwrap().get_format() != 4 {
+    fn get_encoding_format_6_at(&self, index: u16) -> Option<EncodingFormat6<'a>> {
+        let encoding = self.get_encoding(index)?;
+        if encoding.get_format() != 6 {
             return None;
         }
-        let record = self.get_encoding_record(index).unwrap();
-        let subtable_len = get_u16(self.0, (record.get_offset() + 2) as usize).unwrap() as u32;
-        let encoding_data =
-            &self.0[record.get_offset() as usize..(record.get_offset() + subtable_len) as usize];
-        Some(EncodingFormat4(encoding_data))
+        Some(EncodingFormat6(encoding.0))
     }
 
     fn get_encodings(&self) -> Vec<Encoding> {
-        let mut encodings = vec![];
-        for i in 0..self.get_num_tables() {
-            encodings.push(self.get_encoding(i).unwrap());
-        }
-        encodings
+        (0..self.get_num_tables()).filter_map(|i| self.get_encoding(i)).collect()
     }
 
-    pub fn find_format_4_encoding(&self) -> Option<u16> {
+    fn get_encoding_format_12_at(&self, index: u16) -> Option<EncodingFormat12<'a>> {
+        let encoding = self.get_encoding(index)?;
+        if encoding.get_format() != 12 {
+            return None;
+        }
+        Some(EncodingFormat12(encoding.0))
+    }
+
+    /// Picks the subtable used to map codepoints to glyph ids, preferring a
+    /// full-Unicode format-12 subtable -- (platform 3, encoding 10) or
+    /// (platform 0, encoding 6) -- then format 4, which only covers the BMP,
+    /// then format 6 (a trimmed table covering one contiguous code point
+    /// run, seen in some older/CJK fonts as their only subtable). Returns
+    /// the subtable's index and format.
+    pub fn find_best_encoding(&self) -> Option<(u16, u16)> {
+        let mut format4 = None;
+        let mut format6 = None;
         for index in 0..self.get_num_tables() {
-            let encoding = self.get_encoding(index);
-            if let Some(encoding) = encoding {
-                if encoding.get_format() == 4 {
-                    return Some(index);
+            let record = match self.get_encoding_record(index) {
+                Some(r) => r,
+                None => continue,
+            };
+            if let Some(encoding) = self.get_encoding(index) {
+                match encoding.get_format() {
+                    12 if (record.get_platform_id() == 3 && record.get_encoding_id() == 10)
+                        || (record.get_platform_id() == 0 && record.get_encoding_id() == 6) =>
+                    {
+                        return Some((index, 12));
+                    }
+                    4 if format4.is_none() => format4 = Some((index, 4)),
+                    6 if format6.is_none() => format6 = Some((index, 6)),
+                    _ => {}
                 }
             }
         }
-        None
+        format4.or(format6)
     }
 }
 
@@ -454,6 +611,80 @@ enum Glyph<'a> {
     Empty,
     Simple(SimpleGlyph<'a>),
     Compound(CompoundGlyph<'a>),
+    /// A CFF/Type2 outline, already fully executed into a command stream per
+    /// contour (see `cff::Cff::outline`) rather than lazily walked like the
+    /// `glyf`-backed variants, since charstring bytecode isn't a simple
+    /// byte-packed point list. Cubics are kept as true cubics.
+    Cff(Vec<Vec<cff::PathOp>>),
+}
+
+impl<'a> Glyph<'a> {
+    fn bbox(&self) -> Option<(i16, i16, i16, i16)> {
+        match *self {
+            Glyph::Simple(ref s) => Some(s.bbox()),
+            Glyph::Compound(ref c) => Some(c.bbox()),
+            Glyph::Cff(ref contours) => cff_bbox(contours),
+            Glyph::Empty => None,
+        }
+    }
+}
+
+/// CFF charstrings carry no embedded bounding box (unlike `glyf`'s
+/// `SimpleGlyph`/`CompoundGlyph` header), so it has to be derived from the
+/// extremes of the outline itself (control points included, which is a
+/// conservative over-estimate rather than the curve's true extent).
+fn cff_bbox(contours: &[Vec<cff::PathOp>]) -> Option<(i16, i16, i16, i16)> {
+    let mut xmin = f32::INFINITY;
+    let mut ymin = f32::INFINITY;
+    let mut xmax = f32::NEG_INFINITY;
+    let mut ymax = f32::NEG_INFINITY;
+    let mut bound = |p: &Point| {
+        xmin = xmin.min(p.x);
+        ymin = ymin.min(p.y);
+        xmax = xmax.max(p.x);
+        ymax = ymax.max(p.y);
+    };
+    for contour in contours {
+        for op in contour {
+            match *op {
+                cff::PathOp::MoveTo(ref p) | cff::PathOp::LineTo(ref p) => bound(p),
+                cff::PathOp::CurveTo(ref p1, ref p2, ref p3) => {
+                    bound(p1);
+                    bound(p2);
+                    bound(p3);
+                }
+            }
+        }
+    }
+    if xmin > xmax {
+        return None;
+    }
+    Some((
+        xmin.floor() as i16,
+        ymin.floor() as i16,
+        xmax.ceil() as i16,
+        ymax.ceil() as i16,
+    ))
+}
+
+/// Like the `SimpleGlyph` header's bbox, but recomputed from a point list
+/// whose coordinates have already been nudged by `gvar` deltas, since the
+/// header's bbox only describes the default (non-varied) outline.
+fn varied_points_bbox(points: &[(bool, i16, i16)]) -> Option<(i16, i16, i16, i16)> {
+    if points.is_empty() {
+        return None;
+    }
+    let mut xmin = i16::max_value();
+    let mut ymin = i16::max_value();
+    let mut xmax = i16::min_value();
+    let mut ymax = i16::min_value();
+    for &(_, x, y) in points {
+        xmin = xmin.min(x);
+        ymin = ymin.min(y);
+        xmax = xmax.max(x);
+        ymax = ymax.max(y);
+    }
+    Some((xmin, ymin, xmax, ymax))
 }
 
 struct SimpleGlyph<'a> {
@@ -639,6 +870,12 @@ const MORE_COMPONENTS: u16 = 1 << 5;
 const WE_HAVE_AN_X_AND_Y_SCALE: u16 = 1 << 6;
 const WE_HAVE_A_TWO_BY_TWO: u16 = 1 << 7;
 
+/// Deepest chain of compound-glyph components `glyph_outline_inner` will
+/// follow before giving up on the rest of that branch -- a backstop against
+/// a malformed or adversarial font whose components reference each other in
+/// a cycle.
+const MAX_COMPONENT_DEPTH: u32 = 8;
+
 impl<'a> Iterator for Components<'a> {
     type Item = (u16, Affine);
     fn next(&mut self) -> Option<(u16, Affine)> {
@@ -708,6 +945,38 @@ impl<'a> CompoundGlyph<'a> {
     }
 }
 
+/// Patches every component's `glyphIndex` field in a `CompoundGlyph`'s raw
+/// bytes through `remap`, walking the same record layout `Components`
+/// parses. Used by `Font::subset` to renumber composite references onto the
+/// new, dense glyph id space; components missing from `remap` are left
+/// untouched (shouldn't happen since the subset's glyph set is closed over
+/// component references).
+fn remap_compound_glyph(data: &[u8], remap: &BTreeMap<u16, u16>) -> Vec<u8> {
+    let mut out = data.to_vec();
+    let mut ix = 10usize;
+    loop {
+        let flags = get_u16(&out, ix).unwrap();
+        let glyph_index = get_u16(&out, ix + 2).unwrap();
+        if let Some(&new_index) = remap.get(&glyph_index) {
+            out[ix + 2] = (new_index >> 8) as u8;
+            out[ix + 3] = new_index as u8;
+        }
+        ix += 4;
+        ix += if (flags & ARG_1_AND_2_ARE_WORDS) != 0 { 4 } else { 2 };
+        if (flags & WE_HAVE_A_TWO_BY_TWO) != 0 {
+            ix += 8;
+        } else if (flags & WE_HAVE_AN_X_AND_Y_SCALE) != 0 {
+            ix += 4;
+        } else if (flags & WE_HAVE_A_SCALE) != 0 {
+            ix += 2;
+        }
+        if (flags & MORE_COMPONENTS) == 0 {
+            break;
+        }
+    }
+    out
+}
+
 pub struct Font<'a> {
     _version: u32,
     _tables: HashMap<Tag, &'a [u8]>,
@@ -716,9 +985,17 @@ pub struct Font<'a> {
     cmap: Option<Cmap<'a>>,
     loca: Option<Loca<'a>>,
     glyf: Option<&'a [u8]>,
-    encoding_index: Option<u16>,
+    cff: Option<Cff<'a>>,
+    encoding: Option<(u16, u16)>,
     hhea: Option<Hhea<'a>>,
     hmtx: Option<Hmtx<'a>>,
+    post: Option<Post<'a>>,
+    os2: Option<Os2<'a>>,
+    fvar: Option<Fvar<'a>>,
+    avar: Option<Avar>,
+    gvar: Option<Gvar<'a>>,
+    kern: Option<Kern<'a>>,
+    gpos: Option<Gpos<'a>>,
 }
 
 struct Metrics {
@@ -738,10 +1015,65 @@ impl Metrics {
     }
 }
 
+/// Screen-density parameters for converting a point size to a pixel size.
+///
+/// Callers that need to rasterize the same glyph at different physical
+/// resolutions (e.g. a terminal moved between a HiDPI and a regular
+/// display) supply this alongside a point size instead of precomputing a
+/// pixel size themselves.
+pub struct RasterizerConfig {
+    /// Ratio between device pixels and logical (CSS-style) pixels, e.g.
+    /// `2.0` on a typical HiDPI screen.
+    pub device_pixel_ratio: f32,
+    /// Horizontal dots-per-inch of the target device; defaults to 96 (the
+    /// standard reference DPI) when `None`.
+    pub dpi_x: Option<f32>,
+    /// Vertical dots-per-inch; defaults to `dpi_x` when `None`.
+    pub dpi_y: Option<f32>,
+}
+
+const DEFAULT_DPI: f32 = 96.0;
+
+gen_new!(RasterizerConfig, device_pixel_ratio: f32, dpi_x: Option<f32>, dpi_y: Option<f32>);
+
+impl RasterizerConfig {
+    /// A config with no extra DPI scaling, only a device pixel ratio.
+    pub fn with_device_pixel_ratio(device_pixel_ratio: f32) -> RasterizerConfig {
+        RasterizerConfig::new(device_pixel_ratio, None, None)
+    }
+
+    /// Horizontal scale factor to go from a point size to a pixel size.
+    pub fn px_per_pt_x(&self) -> f32 {
+        self.device_pixel_ratio * self.dpi_x.unwrap_or(DEFAULT_DPI) / 72.0
+    }
+
+    /// Vertical scale factor to go from a point size to a pixel size;
+    /// falls back to `dpi_x` when `dpi_y` isn't set, matching the common
+    /// case of a square-pixel display.
+    pub fn px_per_pt_y(&self) -> f32 {
+        let dpi_y = self.dpi_y.or(self.dpi_x).unwrap_or(DEFAULT_DPI);
+        self.device_pixel_ratio * dpi_y / 72.0
+    }
+}
+
+/// Font-wide vertical metrics, scaled to `size` pixels.
+///
+/// These use the same sign convention as the underlying `hhea`/`OS/2`
+/// tables, which is y-up (increasing away from the baseline toward the top
+/// of the glyph), NOT the y-down pixel space `GlyphBitmap` rasterizes into:
+/// `ascent` is positive, `descent` is negative, and `underline_position`/
+/// `strikeout_position` are offsets from the baseline (negative = below it).
 pub struct VMetrics {
     pub ascent: f32,
     pub descent: f32,
     pub line_gap: f32,
+    /// `ascent - descent + line_gap`, the recommended distance between the
+    /// baselines of consecutive lines.
+    pub line_height: f32,
+    pub underline_position: f32,
+    pub underline_thickness: f32,
+    pub strikeout_position: f32,
+    pub strikeout_thickness: f32,
 }
 
 pub struct HMetrics {
@@ -759,88 +1091,382 @@ impl<'a> Font<'a> {
         &self, xmin: i16, ymin: i16, xmax: i16, ymax: i16, size: u32,
     ) -> (Metrics, Affine) {
         let scale = self.scale(size);
-        let l = (xmin as f32 * scale).floor() as i32;
-        let t = (ymax as f32 * -scale).floor() as i32;
-        let r = (xmax as f32 * scale).ceil() as i32;
-        let b = (ymin as f32 * -scale).ceil() as i32;
+        let transform = Affine::new(scale, 0.0, 0.0, -scale, 0.0, 0.0);
+        self.metrics_and_affine_for_transform(xmin, ymin, xmax, ymax, &transform)
+    }
+
+    /// Like `metrics_and_affine`, but for an arbitrary caller-supplied
+    /// transform rather than one derived from a pixel size: maps all four
+    /// bbox corners through `transform` and takes their min/max, so a
+    /// rotation or shear that isn't axis-aligned still gets a tight bitmap.
+    /// The returned `Affine` is `transform` followed by a translation that
+    /// moves those extents to the raster's origin.
+    fn metrics_and_affine_for_transform(
+        &self, xmin: i16, ymin: i16, xmax: i16, ymax: i16, transform: &Affine,
+    ) -> (Metrics, Affine) {
+        let corners = [
+            Point::new(xmin as f32, ymin as f32),
+            Point::new(xmin as f32, ymax as f32),
+            Point::new(xmax as f32, ymin as f32),
+            Point::new(xmax as f32, ymax as f32),
+        ];
+        let mut lo = affine_pt(transform, &corners[0]);
+        let mut hi = lo;
+        for c in &corners[1..] {
+            let p = affine_pt(transform, c);
+            lo.x = lo.x.min(p.x);
+            lo.y = lo.y.min(p.y);
+            hi.x = hi.x.max(p.x);
+            hi.y = hi.y.max(p.y);
+        }
+        let l = lo.x.floor() as i32;
+        let t = lo.y.floor() as i32;
+        let r = hi.x.ceil() as i32;
+        let b = hi.y.ceil() as i32;
         let metrics = Metrics {
             l: l,
             t: t,
             r: r,
             b: b,
         };
-        let z = Affine::new(scale, 0.0, 0.0, -scale, -l as f32, -t as f32);
+        let translate = Affine::new(1.0, 0.0, 0.0, 1.0, -l as f32, -t as f32);
+        let z = Affine::concat(&translate, transform);
         (metrics, z)
     }
 
-    fn render_glyph_inner(&self, raster: &mut Raster, z: &Affine, glyph: &Glyph) {
+    /// Walks `glyph`'s outline (recursing through compound components, just
+    /// like rendering does), appending it to `ops` as a stream of path
+    /// commands already transformed by `z` into the same pixel space
+    /// `render_glyph_inner` rasterizes into. Both the rasterizer and the
+    /// public `glyph_outline` API build on this, so they can't diverge.
+    ///
+    /// `depth` counts compound-component recursion so a glyph that
+    /// (directly or indirectly) references itself can't recurse forever;
+    /// past `MAX_COMPONENT_DEPTH` the offending component is just dropped.
+    fn glyph_outline_inner(&self, z: &Affine, glyph: &Glyph, ops: &mut Vec<PathOp>, depth: u32) {
+        if depth > MAX_COMPONENT_DEPTH {
+            return;
+        }
         match *glyph {
             Glyph::Simple(ref s) => {
                 let mut p = s.points();
                 for n in s.contour_sizes() {
-                    //println!("n = {}", n);
-                    //let v = path_from_pts(p.by_ref().take(n)).collect::<Vec<_>>();
-                    //println!("size = {}", v.len());
-                    draw_path(raster, z, &mut path_from_pts(p.by_ref().take(n)));
+                    for op in path_from_pts(p.by_ref().take(n)) {
+                        ops.push(transform_path_op(z, op));
+                    }
                 }
             }
             Glyph::Compound(ref c) => {
                 for (glyph_index, affine) in c.components() {
-                    //println!("component {} {:?}", glyph_index, affine);
                     let concat = Affine::concat(z, &affine);
                     if let Some(component_glyph) = self.get_glyph(glyph_index) {
-                        self.render_glyph_inner(raster, &concat, &component_glyph);
+                        self.glyph_outline_inner(&concat, &component_glyph, ops, depth + 1);
+                    }
+                }
+            }
+            Glyph::Cff(ref contours) => {
+                for contour in contours {
+                    let mut start = None;
+                    let mut last = Point::new(0.0f32, 0.0f32);
+                    for op in contour {
+                        match *op {
+                            cff::PathOp::MoveTo(p) => {
+                                start = Some(p);
+                                last = p;
+                                ops.push(MoveTo(affine_pt(z, &p)));
+                            }
+                            cff::PathOp::LineTo(p) => {
+                                ops.push(LineTo(affine_pt(z, &p)));
+                                last = p;
+                            }
+                            cff::PathOp::CurveTo(p1, p2, p3) => {
+                                ops.push(CurveTo(affine_pt(z, &p1), affine_pt(z, &p2), affine_pt(z, &p3)));
+                                last = p3;
+                            }
+                        }
+                    }
+                    // Type2 charstrings close a contour implicitly at the
+                    // next moveto/endchar rather than with an explicit
+                    // closepath operator.
+                    if let Some(start) = start {
+                        if last.x != start.x || last.y != start.y {
+                            ops.push(LineTo(affine_pt(z, &start)));
+                        }
                     }
                 }
             }
-            _ => {
-                println!("unhandled glyph case");
+            Glyph::Empty => {}
+        }
+    }
+
+    fn render_glyph_inner(&self, raster: &mut Raster, z: &Affine, glyph: &Glyph) {
+        let mut ops = Vec::new();
+        self.glyph_outline_inner(z, glyph, &mut ops, 0);
+        draw_ops(raster, &mut ops.into_iter());
+    }
+
+    /// Extracts a glyph's outline as a path-command stream (already scaled
+    /// and flipped into the same pixel space `render_glyph`'s bitmap uses),
+    /// without rasterizing it -- for SVG export, GPU tessellation, or
+    /// caching flattened outlines.
+    pub fn glyph_outline(&self, glyph_id: u16, size: u32) -> Option<Outline> {
+        let glyph = self.get_glyph(glyph_id)?;
+        let (xmin, ymin, xmax, ymax) = glyph.bbox()?;
+        let (_, z) = self.metrics_and_affine(xmin, ymin, xmax, ymax, size);
+        let mut ops = Vec::new();
+        self.glyph_outline_inner(&z, &glyph, &mut ops, 0);
+        Some(Outline { ops: ops })
+    }
+
+    /// Like `glyph_outline`, but in the font's own em-unit coordinate space
+    /// (no size, no pixel scale/flip) -- for callers feeding a glyph into
+    /// their own rasterizer, a GPU tessellator, or a vector exporter.
+    pub fn outline(&self, glyph_id: u16) -> Option<impl Iterator<Item = PathOp>> {
+        let glyph = self.get_glyph(glyph_id)?;
+        let identity = Affine::identity();
+        let mut ops = Vec::new();
+        self.glyph_outline_inner(&identity, &glyph, &mut ops, 0);
+        Some(ops.into_iter())
+    }
+
+    /// Like `outline`, but drives an `OutlineBuilder` sink instead of
+    /// handing back a `PathOp` stream -- for callers that want to emit SVG
+    /// paths or feed a GPU tessellator without depending on `PathOp`.
+    pub fn outline_glyph(&self, glyph_id: u16, sink: &mut dyn OutlineBuilder) -> Option<()> {
+        let mut started = false;
+        for op in self.outline(glyph_id)? {
+            match op {
+                MoveTo(p) => {
+                    if started {
+                        sink.close();
+                    }
+                    sink.move_to(p);
+                    started = true;
+                }
+                LineTo(p) => sink.line_to(p),
+                QuadTo(p1, p2) => sink.quad_to(p1, p2),
+                CurveTo(p1, p2, p3) => sink.curve_to(p1, p2, p3),
             }
         }
+        if started {
+            sink.close();
+        }
+        Some(())
+    }
+
+    /// Rasterizes a glyph, returning an error instead of panicking when the
+    /// glyph id is unknown, its outline is missing, or its coordinates are
+    /// degenerate. `render_glyph` is a thin wrapper around this that drops
+    /// the error for backward compatibility.
+    pub fn try_rasterize_glyph(
+        &self, glyph_id: u16, size: u32,
+    ) -> Result<GlyphBitmap, RasterizeError> {
+        let glyph = self.get_glyph(glyph_id).ok_or(RasterizeError::MissingGlyph)?;
+        let (xmin, ymin, xmax, ymax) = glyph.bbox().ok_or(RasterizeError::MissingGlyph)?;
+        // A zero `unitsPerEm` would turn every scaled coordinate non-finite.
+        if !self.scale(size).is_finite() {
+            return Err(RasterizeError::Invalid);
+        }
+        let (metrics, z) = self.metrics_and_affine(xmin, ymin, xmax, ymax, size);
+        let mut raster = Raster::try_new(metrics.width(), metrics.height())?;
+        self.render_glyph_inner(&mut raster, &z, &glyph);
+        Ok(GlyphBitmap {
+            width: metrics.width(),
+            height: metrics.height(),
+            left: metrics.l,
+            top: metrics.t,
+            data: raster.get_bitmap(),
+        })
     }
 
     pub fn render_glyph(&self, glyph_id: u16, size: u32) -> Option<GlyphBitmap> {
-        let glyph = self.get_glyph(glyph_id);
-        match glyph {
-            Some(Glyph::Simple(ref s)) => {
-                let (xmin, ymin, xmax, ymax) = s.bbox();
-                let (metrics, z) = self.metrics_and_affine(xmin, ymin, xmax, ymax, size);
-                let mut raster = Raster::new(metrics.width(), metrics.height());
-                //dump_glyph(SimpleGlyph(s));
-                self.render_glyph_inner(&mut raster, &z, glyph.as_ref().unwrap());
-                //None
-                Some(GlyphBitmap {
-                    width: metrics.width(),
-                    height: metrics.height(),
-                    left: metrics.l,
-                    top: metrics.t,
-                    data: raster.get_bitmap(),
-                })
-            }
-            Some(Glyph::Compound(ref c)) => {
-                let (xmin, ymin, xmax, ymax) = c.bbox();
-                let (metrics, z) = self.metrics_and_affine(xmin, ymin, xmax, ymax, size);
-                let mut raster = Raster::new(metrics.width(), metrics.height());
-                self.render_glyph_inner(&mut raster, &z, glyph.as_ref().unwrap());
-                Some(GlyphBitmap {
-                    width: metrics.width(),
-                    height: metrics.height(),
-                    left: metrics.l,
-                    top: metrics.t,
-                    data: raster.get_bitmap(),
-                })
-            }
-            _ => {
-                println!("glyph {} error", glyph_id);
-                None
+        self.try_rasterize_glyph(glyph_id, size).ok()
+    }
+
+    /// Rasterizes a glyph through a caller-supplied affine instead of a
+    /// plain pixel size, so a rotation, shear (synthetic italic), or
+    /// fractional pen offset can be baked directly into the bitmap. The
+    /// bitmap is sized from `transform` applied to the glyph's bbox
+    /// corners, so it stays tight even when `transform` isn't axis-aligned.
+    pub fn render_glyph_transformed(&self, glyph_id: u16, transform: &Affine) -> Option<GlyphBitmap> {
+        let glyph = self.get_glyph(glyph_id)?;
+        let (xmin, ymin, xmax, ymax) = glyph.bbox()?;
+        let (metrics, z) = self.metrics_and_affine_for_transform(xmin, ymin, xmax, ymax, transform);
+        let mut raster = Raster::try_new(metrics.width(), metrics.height()).ok()?;
+        self.render_glyph_inner(&mut raster, &z, &glyph);
+        Some(GlyphBitmap {
+            width: metrics.width(),
+            height: metrics.height(),
+            left: metrics.l,
+            top: metrics.t,
+            data: raster.get_bitmap(),
+        })
+    }
+
+    /// Rasterizes every glyph id in `start_glyph_id ..= end_glyph_id` at
+    /// `size`, skipping any that fail to rasterize rather than aborting the
+    /// whole batch. Meant for "rasterize A-Z into an atlas" callers that
+    /// would otherwise call `render_glyph` once per id themselves; this
+    /// doesn't yet share parse work across glyphs, but gives them a single
+    /// call site to do so behind later.
+    pub fn render_glyph_range(
+        &self, start_glyph_id: u16, end_glyph_id: u16, size: u32,
+    ) -> Vec<(u16, GlyphBitmap)> {
+        (start_glyph_id ..= end_glyph_id)
+            .filter_map(|glyph_id| self.render_glyph(glyph_id, size).map(|bitmap| (glyph_id, bitmap)))
+            .collect()
+    }
+
+    /// Rasterizes every codepoint covered by `ranges` (each an inclusive
+    /// `(first, last)` pair) at `size` and packs the results into one
+    /// `Atlas`. Each glyph is still rasterized through its own `Raster` by
+    /// `render_glyph`. Glyphs are inserted tallest-first, which packs
+    /// shelves tighter than insertion order would. A codepoint with no
+    /// mapped glyph is simply left out of the returned map, but a bitmap
+    /// that doesn't fit the initial size estimate never is: following
+    /// `Atlas::insert`'s documented contract, the atlas is grown and the
+    /// whole batch re-packed until everything fits.
+    pub fn render_range(&self, ranges: &[(u32, u32)], size: u32) -> GlyphAtlas {
+        let mut bitmaps: Vec<(u32, GlyphBitmap)> = ranges
+            .iter()
+            .flat_map(|&(first, last)| first..=last)
+            .filter_map(|cp| {
+                let glyph_id = self.glyph_for_codepoint(cp)?;
+                self.render_glyph(glyph_id, size).map(|bitmap| (cp, bitmap))
+            })
+            .collect();
+        bitmaps.sort_by(|a, b| b.1.height.cmp(&a.1.height));
+
+        let total_area: usize = bitmaps.iter().map(|&(_, ref b)| b.width * b.height).sum();
+        let max_width = bitmaps.iter().map(|&(_, ref b)| b.width).max().unwrap_or(1);
+        let max_height = bitmaps.iter().map(|&(_, ref b)| b.height).max().unwrap_or(1);
+        let width = ((total_area as f32).sqrt().ceil() as usize).max(max_width);
+        let mut height = (total_area / width.max(1) + max_height + 1).max(1);
+
+        loop {
+            if let Some((atlas, glyphs)) = Self::try_pack_atlas(width, height, &bitmaps) {
+                return GlyphAtlas { atlas: atlas, glyphs: glyphs };
             }
+            // Shelf packing wastes a row's leftover height whenever a row's
+            // glyphs are shorter than its tallest one, so the sqrt-area
+            // estimate above can undershoot; grow height (width already
+            // covers the widest single glyph) and re-pack from scratch.
+            height += height / 2 + max_height + 1;
+        }
+    }
+
+    /// Packs `bitmaps` into a fresh `width x height` `Atlas`, or `None` if
+    /// any of them doesn't fit -- the caller's cue to grow and retry.
+    fn try_pack_atlas(
+        width: usize, height: usize, bitmaps: &[(u32, GlyphBitmap)],
+    ) -> Option<(Atlas, BTreeMap<u32, AtlasGlyph>)> {
+        let mut atlas = Atlas::new(width, height);
+        let mut glyphs = BTreeMap::new();
+        for &(cp, ref bitmap) in bitmaps {
+            let rect = atlas.insert(bitmap.width, bitmap.height)?;
+            atlas.blit(&rect, &bitmap.data);
+            glyphs.insert(cp, AtlasGlyph {
+                x: rect.x,
+                y: rect.y,
+                width: rect.w,
+                height: rect.h,
+                left: bitmap.left,
+                top: bitmap.top,
+            });
+        }
+        Some((atlas, glyphs))
+    }
+
+    /// Rasterizes a glyph from a point size and a screen density, applying
+    /// the `pts -> px` conversion internally so callers don't have to
+    /// reimplement the DPI/device-pixel-ratio math at every call site.
+    pub fn render_glyph_at_point_size(
+        &self, glyph_id: u16, point_size: f32, config: &RasterizerConfig,
+    ) -> Option<GlyphBitmap> {
+        let px_size = (point_size * config.px_per_pt_x()).max(0.0).round() as u32;
+        self.render_glyph(glyph_id, px_size)
+    }
+
+    /// Rasterizes a glyph at a non-default point in variation space,
+    /// applying `gvar` deltas (with IUP-interpolated fill-in for points the
+    /// tuple variation store doesn't cover) to a `SimpleGlyph`'s points
+    /// before rendering. `coords` are user-space values in `fvar` axis
+    /// order; missing trailing axes default to that axis's default value.
+    ///
+    /// Falls back to the default (non-varied) instance for compound and
+    /// CFF glyphs, which this doesn't yet apply deltas to.
+    pub fn render_glyph_variation(
+        &self, glyph_id: u16, size: u32, coords: &[f32],
+    ) -> Option<GlyphBitmap> {
+        let fvar = match self.fvar {
+            Some(ref fvar) => fvar,
+            None => return self.render_glyph(glyph_id, size),
+        };
+        if self.gvar.is_none() {
+            return self.render_glyph(glyph_id, size);
+        }
+        let norm_coords = variations::normalize_coords(fvar, self.avar.as_ref(), coords);
+        self.render_glyph_var(glyph_id, size, &norm_coords)
+    }
+
+    /// Like `render_glyph_variation`, but `coords` are already normalized
+    /// (each an F2Dot14-range value in `[-1, 1]`, one per `fvar` axis in
+    /// order) rather than user-space -- for callers that have already done
+    /// their own `avar` remapping, or that only deal in the wire encoding.
+    pub fn render_glyph_var(
+        &self, glyph_id: u16, size: u32, norm_coords: &[f32],
+    ) -> Option<GlyphBitmap> {
+        let gvar = match self.gvar {
+            Some(ref gvar) => gvar,
+            None => return self.render_glyph(glyph_id, size),
+        };
+        let glyph = self.get_glyph(glyph_id)?;
+        let s = match glyph {
+            Glyph::Simple(ref s) => s,
+            _ => return self.render_glyph(glyph_id, size),
+        };
+
+        let points: Vec<(bool, i16, i16)> = s.points().collect();
+        let plain_points: Vec<(i16, i16)> = points.iter().map(|&(_, x, y)| (x, y)).collect();
+        let mut contour_ends = Vec::new();
+        let mut acc = 0usize;
+        for n in s.contour_sizes() {
+            acc += n;
+            contour_ends.push(acc - 1);
         }
+        let deltas = gvar.glyph_deltas(glyph_id, &plain_points, &contour_ends, &norm_coords);
+        let varied: Vec<(bool, i16, i16)> = points
+            .iter()
+            .zip(deltas.iter())
+            .map(|(&(on, x, y), &(dx, dy))| (on, (x as f32 + dx).round() as i16, (y as f32 + dy).round() as i16))
+            .collect();
+
+        let (xmin, ymin, xmax, ymax) = varied_points_bbox(&varied)?;
+        let (metrics, z) = self.metrics_and_affine(xmin, ymin, xmax, ymax, size);
+        let mut raster = Raster::try_new(metrics.width(), metrics.height()).ok()?;
+        let mut ix = 0;
+        for n in s.contour_sizes() {
+            let mut ops = path_from_pts(varied[ix..ix + n].iter().cloned())
+                .map(|op| transform_path_op(&z, op));
+            draw_ops(&mut raster, &mut ops);
+            ix += n;
+        }
+        Some(GlyphBitmap {
+            width: metrics.width(),
+            height: metrics.height(),
+            left: metrics.l,
+            top: metrics.t,
+            data: raster.get_bitmap(),
+        })
     }
 
     fn get_glyph(&self, glyph_ix: u16) -> Option<Glyph> {
         if glyph_ix >= self.maxp.num_glyphs() {
             return None;
         }
+        if let Some(ref cff) = self.cff {
+            return cff.outline(glyph_ix).map(Glyph::Cff);
+        }
         let fmt = self.head.index_to_loc_format();
         match self.loca {
             Some(ref loca) => match (
@@ -864,21 +1490,32 @@ impl<'a> Font<'a> {
         }
     }
 
-    pub fn lookup_glyph_id(&self, code_point: u32) -> Option<u16> {
-        match self.encoding_index {
-            Some(encoding_index) => {
+    /// Maps a Unicode codepoint to a glyph id via the font's best `cmap`
+    /// subtable (format 12 preferred, falling back to 4 or 6), so callers
+    /// can render directly from text instead of needing to know the font's
+    /// internal glyph numbering.
+    pub fn glyph_for_codepoint(&self, code_point: u32) -> Option<u16> {
+        let (index, format) = self.encoding?;
+        let cmap = self.cmap.as_ref().unwrap();
+        match format {
+            4 => {
                 if code_point > u16::max_value() as u32 {
                     return None;
                 }
-
-                self.cmap
-                    .as_ref()
+                cmap.get_encoding_format_4_at(index)
                     .unwrap()
-                    .get_encoding_format_4_at(encoding_index)
+                    .lookup_glyph_id(code_point as u16)
+            }
+            12 => cmap.get_encoding_format_12_at(index).unwrap().lookup_glyph_id(code_point),
+            6 => {
+                if code_point > u16::max_value() as u32 {
+                    return None;
+                }
+                cmap.get_encoding_format_6_at(index)
                     .unwrap()
                     .lookup_glyph_id(code_point as u16)
             }
-            None => None,
+            _ => None,
         }
     }
 
@@ -891,10 +1528,17 @@ impl<'a> Font<'a> {
             ) {
                 (Some(ascent), Some(descent), Some(line_gap)) => {
                     let scale = self.scale(size);
+                    let (underline_position, underline_thickness) = self.underline_metrics(descent);
+                    let (strikeout_position, strikeout_thickness) = self.strikeout_metrics(ascent, descent);
                     Some(VMetrics {
                         ascent: ascent as f32 * scale,
                         descent: descent as f32 * scale,
                         line_gap: line_gap as f32 * scale,
+                        line_height: (ascent - descent + line_gap) as f32 * scale,
+                        underline_position: underline_position as f32 * scale,
+                        underline_thickness: underline_thickness as f32 * scale,
+                        strikeout_position: strikeout_position as f32 * scale,
+                        strikeout_thickness: strikeout_thickness as f32 * scale,
                     })
                 },
                 (_, _, _) => None,
@@ -904,6 +1548,35 @@ impl<'a> Font<'a> {
         }
     }
 
+    // When `post` doesn't carry an underline, synthesize one the way
+    // most rasterizers do: a thickness of a fifth of the descent depth,
+    // centered half a descent below the baseline.
+    fn underline_metrics(&self, descent: i16) -> (i16, i16) {
+        let synth_thickness = (descent as f32 / -5.0).round() as i16;
+        let synth_position = descent / 2;
+        match self.post {
+            Some(ref post) => (
+                post.underline_position().unwrap_or(synth_position),
+                post.underline_thickness().unwrap_or(synth_thickness),
+            ),
+            None => (synth_position, synth_thickness),
+        }
+    }
+
+    fn strikeout_metrics(&self, ascent: i16, descent: i16) -> (i16, i16) {
+        let synth_thickness = (descent as f32 / -5.0).round() as i16;
+        // A reasonable default strikeout sits roughly at the x-height,
+        // which we approximate as the midpoint between baseline and ascent.
+        let synth_position = ascent / 2;
+        match self.os2 {
+            Some(ref os2) => (
+                os2.strikeout_position().unwrap_or(synth_position),
+                os2.strikeout_size().unwrap_or(synth_thickness),
+            ),
+            None => (synth_position, synth_thickness),
+        }
+    }
+
     pub fn get_h_metrics(&self, glyph_id: u16, size: u32) -> Option<HMetrics> {
         if let (Some(ref hhea), Some(ref hmtx)) = (&self.hhea, &self.hmtx) {
             if let Some(num_of_long_hor_metrics) = hhea.num_of_long_hor_metrics() {
@@ -924,16 +1597,241 @@ impl<'a> Font<'a> {
             None
         }
     }
+
+    /// The legacy `kern` table's pair adjustment for `(left_glyph,
+    /// right_glyph)`, scaled to `size` like `get_h_metrics`'s advance width.
+    /// GPOS PairPos isn't consulted here; use `kerning_adjustments` for a
+    /// run that should prefer GPOS.
+    pub fn get_kerning(&self, left_glyph: u16, right_glyph: u16, size: u32) -> Option<f32> {
+        let kern = self.kern.as_ref()?;
+        let value = kern.lookup(left_glyph, right_glyph)?;
+        Some(value as f32 * self.scale(size))
+    }
+
+    /// Per-glyph `(x_advance, x_offset, y_offset)` adjustments for laying
+    /// out `glyphs` as a run, preferring GPOS pair positioning over the
+    /// legacy `kern` table for any pair both cover. Adjustments are in font
+    /// units; scale by the same factor used for advances/metrics at a given
+    /// size.
+    pub fn kerning_adjustments(&self, glyphs: &[u16]) -> Vec<kerning::GlyphAdjustment> {
+        kerning::adjustments(self.gpos.as_ref(), self.kern.as_ref(), glyphs)
+    }
+
+    /// The transitive closure of `glyphs` under `CompoundGlyph` component
+    /// references (plus glyph 0, `.notdef`, which every `.ttf` needs), so a
+    /// subset font keeps every composite's parts intact.
+    fn close_glyph_set(&self, glyphs: &BTreeSet<u16>) -> BTreeSet<u16> {
+        let mut closure: BTreeSet<u16> = glyphs.iter().cloned().collect();
+        closure.insert(0);
+        let mut stack: Vec<u16> = closure.iter().cloned().collect();
+        while let Some(id) = stack.pop() {
+            if let Some(Glyph::Compound(ref c)) = self.get_glyph(id) {
+                for (component_id, _) in c.components() {
+                    if closure.insert(component_id) {
+                        stack.push(component_id);
+                    }
+                }
+            }
+        }
+        closure
+    }
+
+    /// Builds a new, standalone `.ttf` containing only `glyphs`, closed over
+    /// composite component references so composites stay intact. Glyph ids
+    /// are renumbered densely in sorted order; `glyf`/`loca` are rewritten
+    /// (picking a short or long `indexToLocFormat` based on the new total
+    /// size) and `hmtx`/`maxp`/`head`/`hhea` are trimmed to match. Every
+    /// other table (`cmap`, `kern`, `GPOS`, `post`, ...) is carried over
+    /// byte-for-byte and still refers to *original* glyph ids -- callers
+    /// relying on those (codepoint lookup, kerning) need to re-resolve
+    /// through the same closure/renumbering this method computes internally
+    /// but doesn't yet expose.
+    ///
+    /// Only TrueType (`glyf`/`loca`) outlines are supported; CFF-backed
+    /// fonts return `Err(FontError::Invalid)`.
+    pub fn subset(&self, glyphs: &BTreeSet<u16>) -> Result<Vec<u8>, FontError> {
+        if self.cff.is_some() || self.glyf.is_none() || self.loca.is_none() {
+            return Err(FontError::Invalid);
+        }
+        let hhea = self.hhea.as_ref().ok_or(FontError::Invalid)?;
+        let hmtx = self.hmtx.as_ref().ok_or(FontError::Invalid)?;
+        let num_h_metrics = hhea.num_of_long_hor_metrics().ok_or(FontError::Invalid)?;
+
+        let old_ids: Vec<u16> = self.close_glyph_set(glyphs).into_iter().collect();
+        let remap: BTreeMap<u16, u16> = old_ids
+            .iter()
+            .enumerate()
+            .map(|(new_id, &old_id)| (old_id, new_id as u16))
+            .collect();
+
+        let mut new_glyf = Vec::new();
+        let mut glyph_offsets = Vec::with_capacity(old_ids.len() + 1);
+        for &old_id in &old_ids {
+            glyph_offsets.push(new_glyf.len() as u32);
+            match self.get_glyph(old_id).ok_or(FontError::Invalid)? {
+                Glyph::Empty => {}
+                Glyph::Simple(ref s) => new_glyf.extend_from_slice(s.data),
+                Glyph::Compound(ref c) => new_glyf.extend_from_slice(&remap_compound_glyph(c.data, &remap)),
+                Glyph::Cff(_) => unreachable!("guarded by the CFF check above"),
+            }
+            if new_glyf.len() % 2 != 0 {
+                new_glyf.push(0);
+            }
+        }
+        glyph_offsets.push(new_glyf.len() as u32);
+
+        // Short loca entries are the byte offset / 2, so they can only
+        // address up to 0x1fffe bytes.
+        let long_format = *glyph_offsets.last().unwrap() > 0x1fffe;
+        let mut new_loca = Vec::new();
+        for &off in &glyph_offsets {
+            if long_format {
+                put_u32(&mut new_loca, off);
+            } else {
+                put_u16(&mut new_loca, (off / 2) as u16);
+            }
+        }
+
+        let mut new_head = self.head.0.to_vec();
+        for b in &mut new_head[8..12] {
+            *b = 0; // checksumAdjustment, recomputed once the whole font is laid out
+        }
+        new_head[50] = 0;
+        new_head[51] = if long_format { 1 } else { 0 };
+
+        let mut new_maxp = self.maxp.data.to_vec();
+        new_maxp[4] = (old_ids.len() >> 8) as u8;
+        new_maxp[5] = old_ids.len() as u8;
+
+        let mut new_hmtx = Vec::new();
+        for &old_id in &old_ids {
+            match hmtx.get_h_metrics(old_id, num_h_metrics) {
+                (Some(advance_width), Some(left_side_bearing)) => {
+                    put_u16(&mut new_hmtx, advance_width);
+                    put_i16(&mut new_hmtx, left_side_bearing);
+                }
+                _ => return Err(FontError::Invalid),
+            }
+        }
+        let mut new_hhea = hhea.0.to_vec();
+        new_hhea[34] = (old_ids.len() >> 8) as u8;
+        new_hhea[35] = old_ids.len() as u8;
+
+        let Tag(glyf_tag) = Tag::from_str("glyf");
+        let Tag(loca_tag) = Tag::from_str("loca");
+        let Tag(maxp_tag) = Tag::from_str("maxp");
+        let Tag(head_tag) = Tag::from_str("head");
+        let Tag(hmtx_tag) = Tag::from_str("hmtx");
+        let Tag(hhea_tag) = Tag::from_str("hhea");
+
+        let mut tables: Vec<(u32, Vec<u8>)> = self
+            ._tables
+            .iter()
+            .map(|(tag, &data)| {
+                let &Tag(tag_val) = tag;
+                let bytes = if tag_val == glyf_tag {
+                    new_glyf.clone()
+                } else if tag_val == loca_tag {
+                    new_loca.clone()
+                } else if tag_val == maxp_tag {
+                    new_maxp.clone()
+                } else if tag_val == head_tag {
+                    new_head.clone()
+                } else if tag_val == hmtx_tag {
+                    new_hmtx.clone()
+                } else if tag_val == hhea_tag {
+                    new_hhea.clone()
+                } else {
+                    data.to_vec()
+                };
+                (tag_val, bytes)
+            })
+            .collect();
+        tables.sort_by_key(|&(tag_val, _)| tag_val);
+
+        let num_tables = tables.len() as u16;
+        let mut search_range_pow2 = 1u16;
+        let mut entry_selector = 0u16;
+        while search_range_pow2 * 2 <= num_tables {
+            search_range_pow2 *= 2;
+            entry_selector += 1;
+        }
+        let search_range = search_range_pow2 * 16;
+        let range_shift = num_tables * 16 - search_range;
+
+        let mut out = Vec::new();
+        put_u32(&mut out, self._version);
+        put_u16(&mut out, num_tables);
+        put_u16(&mut out, search_range);
+        put_u16(&mut out, entry_selector);
+        put_u16(&mut out, range_shift);
+
+        let header_len = 12 + 16 * tables.len();
+        let mut body = Vec::new();
+        let mut records = Vec::with_capacity(tables.len());
+        for &(tag_val, ref data) in &tables {
+            let checksum = table_checksum(data);
+            let table_offset = (header_len + body.len()) as u32;
+            records.push((tag_val, checksum, table_offset, data.len() as u32));
+            body.extend_from_slice(data);
+            while body.len() % 4 != 0 {
+                body.push(0);
+            }
+        }
+        for &(tag_val, checksum, table_offset, length) in &records {
+            put_u32(&mut out, tag_val);
+            put_u32(&mut out, checksum);
+            put_u32(&mut out, table_offset);
+            put_u32(&mut out, length);
+        }
+        let head_offset = records
+            .iter()
+            .find(|&&(tag_val, _, _, _)| tag_val == head_tag)
+            .map(|&(_, _, table_offset, _)| table_offset as usize);
+        out.extend_from_slice(&body);
+
+        if let Some(head_offset) = head_offset {
+            let adjustment = 0xB1B0AFBAu32.wrapping_sub(table_checksum(&out));
+            out[head_offset + 8] = (adjustment >> 24) as u8;
+            out[head_offset + 9] = (adjustment >> 16) as u8;
+            out[head_offset + 10] = (adjustment >> 8) as u8;
+            out[head_offset + 11] = adjustment as u8;
+        }
+        Ok(out)
+    }
 }
 
-#[derive(Debug)]
-enum PathOp {
+/// One command in a glyph outline's path stream. `QuadTo` comes from
+/// `glyf`'s quadratic contours; `CurveTo` from a CFF charstring's cubics.
+#[derive(Debug, Clone, Copy)]
+pub enum PathOp {
     MoveTo(Point),
     LineTo(Point),
     QuadTo(Point, Point),
+    CurveTo(Point, Point, Point),
 }
 
-use self::PathOp::{LineTo, MoveTo, QuadTo};
+use self::PathOp::{CurveTo, LineTo, MoveTo, QuadTo};
+
+/// A glyph's outline as returned by `Font::glyph_outline`: an ordered path
+/// command stream, already scaled and flipped into the same pixel space
+/// `render_glyph`'s bitmap uses.
+pub struct Outline {
+    pub ops: Vec<PathOp>,
+}
+
+/// A sink for glyph outlines, decoupled from `PathOp`/`Raster` -- implement
+/// this to emit SVG paths, feed a GPU tessellator, or drive your own
+/// rasterizer from `Font::outline_glyph` without depending on this crate's
+/// internal path representation. Points are in font (em) units, contour by
+/// contour; `close` is called once per contour, including the last.
+pub trait OutlineBuilder {
+    fn move_to(&mut self, p: Point);
+    fn line_to(&mut self, p: Point);
+    fn quad_to(&mut self, p1: Point, p2: Point);
+    fn curve_to(&mut self, p1: Point, p2: Point, p3: Point);
+    fn close(&mut self);
+}
 
 struct BezPathOps<T> {
     inner: T,
@@ -1039,6 +1937,23 @@ pub enum FontError {
     Invalid,
 }
 
+/// Errors returned by the fallible rendering entry points instead of
+/// panicking or silently returning `None`.
+#[derive(Debug, PartialEq)]
+pub enum RasterizeError {
+    /// The glyph id has no outline (unknown id, or missing glyph data).
+    MissingGlyph,
+    /// The outline or requested size produced degenerate rasterizer input,
+    /// such as a non-finite coordinate or an overflowing bitmap size.
+    Invalid,
+}
+
+impl From<RasterError> for RasterizeError {
+    fn from(_: RasterError) -> RasterizeError {
+        RasterizeError::Invalid
+    }
+}
+
 pub fn parse(data: &[u8]) -> Result<Font, FontError> {
     if data.len() < 12 {
         return Err(FontError::Invalid);
@@ -1065,10 +1980,18 @@ pub fn parse(data: &[u8]) -> Result<Font, FontError> {
     };
     let loca = tables.get(&Tag::from_str("loca")).map(|&data| Loca(data));
     let glyf = tables.get(&Tag::from_str("glyf")).map(|&data| data);
+    let cff = tables.get(&Tag::from_str("CFF ")).and_then(|&data| Cff::parse(data));
     let cmap = tables.get(&Tag::from_str("cmap")).map(|&data| Cmap(data));
-    let encoding_index = cmap.as_ref().and_then(|cmap| cmap.find_format_4_encoding());
+    let encoding = cmap.as_ref().and_then(|cmap| cmap.find_best_encoding());
     let hhea = tables.get(&Tag::from_str("hhea")).map(|&data| Hhea(data));
     let hmtx = tables.get(&Tag::from_str("hmtx")).map(|&data| Hmtx(data));
+    let post = tables.get(&Tag::from_str("post")).map(|&data| Post(data));
+    let os2 = tables.get(&Tag::from_str("OS/2")).map(|&data| Os2(data));
+    let fvar = tables.get(&Tag::from_str("fvar")).and_then(|&data| Fvar::parse(data));
+    let avar = tables.get(&Tag::from_str("avar")).and_then(|&data| Avar::parse(data));
+    let gvar = tables.get(&Tag::from_str("gvar")).and_then(|&data| Gvar::parse(data));
+    let kern = tables.get(&Tag::from_str("kern")).and_then(|&data| Kern::parse(data));
+    let gpos = tables.get(&Tag::from_str("GPOS")).and_then(|&data| Gpos::parse(data));
     let f = Font {
         _version: version,
         _tables: tables,
@@ -1077,9 +2000,17 @@ pub fn parse(data: &[u8]) -> Result<Font, FontError> {
         loca: loca,
         cmap: cmap,
         glyf: glyf,
-        encoding_index: encoding_index,
+        cff: cff,
+        encoding: encoding,
         hhea: hhea,
         hmtx: hmtx,
+        post: post,
+        os2: os2,
+        fvar: fvar,
+        avar: avar,
+        gvar: gvar,
+        kern: kern,
+        gpos: gpos,
     };
     //println!("version = {:x}", version);
     Ok(f)
@@ -1129,23 +2060,36 @@ fn dump(data: Vec<u8>) {
 }
 */
 
-fn draw_path<I: Iterator<Item = PathOp>>(r: &mut Raster, z: &Affine, path: &mut I) {
+/// Transforms a single path command's points by `z`, so `glyph_outline_inner`
+/// can emit already-transformed commands and `draw_ops` can just replay them.
+fn transform_path_op(z: &Affine, op: PathOp) -> PathOp {
+    match op {
+        MoveTo(p) => MoveTo(affine_pt(z, &p)),
+        LineTo(p) => LineTo(affine_pt(z, &p)),
+        QuadTo(p1, p2) => QuadTo(affine_pt(z, &p1), affine_pt(z, &p2)),
+        CurveTo(p1, p2, p3) => CurveTo(affine_pt(z, &p1), affine_pt(z, &p2), affine_pt(z, &p3)),
+    }
+}
+
+/// Draws a command stream whose points are already in the raster's pixel
+/// space (as produced by `glyph_outline_inner`/`glyph_outline`).
+fn draw_ops<I: Iterator<Item = PathOp>>(r: &mut Raster, path: &mut I) {
     let mut lastp = Point::new(0i16, 0i16);
     for op in path {
         match op {
             MoveTo(p) => lastp = p,
             LineTo(p) => {
-                r.draw_line(&affine_pt(z, &lastp), &affine_pt(z, &p));
+                r.draw_line(&lastp, &p);
                 lastp = p
             }
             QuadTo(p1, p2) => {
-                r.draw_quad(
-                    &affine_pt(z, &lastp),
-                    &affine_pt(z, &p1),
-                    &affine_pt(z, &p2),
-                );
+                r.draw_quad(&lastp, &p1, &p2);
                 lastp = p2;
             }
+            CurveTo(p1, p2, p3) => {
+                r.draw_cubic(&lastp, &p1, &p2, &p3);
+                lastp = p3;
+            }
         }
     }
 }
@@ -1158,6 +2102,25 @@ pub struct GlyphBitmap {
     pub data: Vec<u8>,
 }
 
+/// Where one glyph rasterized by `Font::render_range` landed in its
+/// `GlyphAtlas`: `x`/`y`/`width`/`height` are the atlas-texture rect,
+/// `left`/`top` are the same pen-offset fields `GlyphBitmap` carries.
+pub struct AtlasGlyph {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+    pub left: i32,
+    pub top: i32,
+}
+
+/// The result of `Font::render_range`: one packed coverage texture plus a
+/// map from codepoint to where its glyph landed in it.
+pub struct GlyphAtlas {
+    pub atlas: Atlas,
+    pub glyphs: BTreeMap<u32, AtlasGlyph>,
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -1172,14 +2135,14 @@ mod tests {
         let cmap = font.cmap.as_ref().unwrap();
         assert!(cmap.get_encoding_record(cmap.get_num_tables()).is_none());
         assert!(cmap.get_encoding(cmap.get_num_tables()).is_none());
-        assert_eq!(font.lookup_glyph_id('A' as u32).unwrap(), 36);
-        assert_eq!(font.lookup_glyph_id(0x3c8).unwrap(), 405);
-        assert_eq!(font.lookup_glyph_id(0xfffd).unwrap(), 589);
-        assert_eq!(font.lookup_glyph_id(0x232B).is_none(), true);
-        assert_eq!(font.lookup_glyph_id(0x1000232B).is_none(), true);
+        assert_eq!(font.glyph_for_codepoint('A' as u32).unwrap(), 36);
+        assert_eq!(font.glyph_for_codepoint(0x3c8).unwrap(), 405);
+        assert_eq!(font.glyph_for_codepoint(0xfffd).unwrap(), 589);
+        assert_eq!(font.glyph_for_codepoint(0x232B).is_none(), true);
+        assert_eq!(font.glyph_for_codepoint(0x1000232B).is_none(), true);
         // test for panics
         for i in 0..0x1ffff {
-            font.lookup_glyph_id(i);
+            font.glyph_for_codepoint(i);
         }
     }
 
@@ -1205,4 +2168,103 @@ mod tests {
         }
         assert!(encoding4.lookup_glyph_id('\n' as u16).is_none());
     }
+
+    // A spec-compliant format-12 subtable header is `format:u16, reserved:u16
+    // (always 0), length:u32, ...`; `reserved` sitting where format 4/6's
+    // length field would be is what made every real format-12 subtable trip
+    // Cmap::get_encoding's old always-u16-at-offset-2 length read.
+    fn build_cmap_format_12(groups: &[(u32, u32, u32)]) -> Vec<u8> {
+        let mut subtable = vec![];
+        subtable.extend_from_slice(&12u16.to_be_bytes()); // format
+        subtable.extend_from_slice(&0u16.to_be_bytes()); // reserved
+        subtable.extend_from_slice(&0u32.to_be_bytes()); // length placeholder
+        subtable.extend_from_slice(&0u32.to_be_bytes()); // language
+        subtable.extend_from_slice(&(groups.len() as u32).to_be_bytes()); // numGroups
+        for &(start, end, glyph) in groups {
+            subtable.extend_from_slice(&start.to_be_bytes());
+            subtable.extend_from_slice(&end.to_be_bytes());
+            subtable.extend_from_slice(&glyph.to_be_bytes());
+        }
+        let len = subtable.len() as u32;
+        subtable[4..8].copy_from_slice(&len.to_be_bytes());
+
+        let mut data = vec![];
+        data.extend_from_slice(&0u16.to_be_bytes()); // cmap version
+        data.extend_from_slice(&1u16.to_be_bytes()); // numTables
+        data.extend_from_slice(&3u16.to_be_bytes()); // platformID (Windows)
+        data.extend_from_slice(&10u16.to_be_bytes()); // encodingID (full Unicode)
+        let subtable_offset = (data.len() + 4) as u32;
+        data.extend_from_slice(&subtable_offset.to_be_bytes());
+        data.extend_from_slice(&subtable);
+        data
+    }
+
+    #[test]
+    fn cmap_format_12_real_world_subtable_does_not_panic() {
+        use font::Cmap;
+        let data = build_cmap_format_12(&[(0x4e00, 0x9fff, 600), (0x1f600, 0x1f600, 50000)]);
+        let cmap = Cmap(&data);
+        assert_eq!(cmap.find_best_encoding(), Some((0, 12)));
+        let enc = cmap.get_encoding_format_12_at(0).unwrap();
+        assert_eq!(enc.lookup_glyph_id(0x4e01), Some(601));
+        assert_eq!(enc.lookup_glyph_id(0x1f600), Some(50000));
+        assert_eq!(enc.lookup_glyph_id(0x20000), None);
+    }
+
+    #[test]
+    fn cmap_format_12_truncated_subtable_does_not_panic() {
+        use font::Cmap;
+        let mut data = build_cmap_format_12(&[(0x4e00, 0x9fff, 600)]);
+        // Chop off the last few bytes of the group array the header's own
+        // `length` still claims are there.
+        data.truncate(data.len() - 4);
+        let cmap = Cmap(&data);
+        assert!(cmap.get_encoding_format_12_at(0).is_none());
+        assert_eq!(cmap.find_best_encoding(), None);
+    }
+
+    fn build_cmap_format_6(first_code: u16, glyph_ids: &[u16]) -> Vec<u8> {
+        let mut subtable = vec![];
+        subtable.extend_from_slice(&6u16.to_be_bytes()); // format
+        subtable.extend_from_slice(&0u16.to_be_bytes()); // length placeholder
+        subtable.extend_from_slice(&0u16.to_be_bytes()); // language
+        subtable.extend_from_slice(&first_code.to_be_bytes());
+        subtable.extend_from_slice(&(glyph_ids.len() as u16).to_be_bytes());
+        for &g in glyph_ids {
+            subtable.extend_from_slice(&g.to_be_bytes());
+        }
+        let len = subtable.len() as u16;
+        subtable[2..4].copy_from_slice(&len.to_be_bytes());
+
+        let mut data = vec![];
+        data.extend_from_slice(&0u16.to_be_bytes()); // cmap version
+        data.extend_from_slice(&1u16.to_be_bytes()); // numTables
+        data.extend_from_slice(&1u16.to_be_bytes()); // platformID
+        data.extend_from_slice(&0u16.to_be_bytes()); // encodingID
+        let subtable_offset = (data.len() + 4) as u32;
+        data.extend_from_slice(&subtable_offset.to_be_bytes());
+        data.extend_from_slice(&subtable);
+        data
+    }
+
+    #[test]
+    fn cmap_format_6_lookup() {
+        use font::Cmap;
+        let data = build_cmap_format_6(100, &[10, 11, 0, 13]);
+        let cmap = Cmap(&data);
+        let enc = cmap.get_encoding_format_6_at(0).unwrap();
+        assert_eq!(enc.lookup_glyph_id(100), Some(10));
+        assert_eq!(enc.lookup_glyph_id(102), None); // glyph id 0 -> unmapped
+        assert_eq!(enc.lookup_glyph_id(99), None); // before firstCode
+        assert_eq!(enc.lookup_glyph_id(104), None); // past entryCount
+    }
+
+    #[test]
+    fn cmap_format_6_truncated_subtable_does_not_panic() {
+        use font::Cmap;
+        let mut data = build_cmap_format_6(100, &[10, 11, 12, 13]);
+        data.truncate(data.len() - 4);
+        let cmap = Cmap(&data);
+        assert!(cmap.get_encoding_format_6_at(0).is_none());
+    }
 }
@@ -47,7 +47,7 @@ impl Debug for Point {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct Affine {
     a: f32,
     b: f32,
@@ -58,6 +58,16 @@ pub struct Affine {
 }
 
 impl Affine {
+    /// The identity transform: `affine_pt` maps every point to itself.
+    pub fn identity() -> Affine {
+        Affine::new(1.0, 0.0, 0.0, 1.0, 0.0, 0.0)
+    }
+
+    /// Applies this transform to a point. Equivalent to `affine_pt(self, p)`.
+    pub fn apply(&self, p: &Point) -> Point {
+        affine_pt(self, p)
+    }
+
     /// Concatenate two affine transforms.
     pub fn concat(t1: &Affine, t2: &Affine) -> Affine {
         Affine {
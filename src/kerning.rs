@@ -0,0 +1,520 @@
+// Copyright 2021 Google Inc. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Pairwise glyph positioning: the legacy `kern` table (format 0) and a
+//! minimal OpenType GPOS lookup type 2 (PairPos) reader. `Hmtx` alone only
+//! gives each glyph's own advance; this fills in the per-pair adjustments
+//! ("AV", "To", ...) that make advance-only layout look loose.
+
+// Bounds-checked reads, mirroring `font.rs`'s `get_u16`/`get_u32` (also the
+// pattern `cff.rs` and `variations.rs` use): `kern`/`GPOS` table bytes come
+// straight from the font file, so a truncated or malformed table must fail
+// gracefully instead of indexing past the end of the slice.
+fn get_u16(data: &[u8], off: usize) -> Option<u16> {
+    if off + 2 > data.len() {
+        None
+    } else {
+        Some(((data[off] as u16) << 8) | data[off + 1] as u16)
+    }
+}
+
+fn get_i16(data: &[u8], off: usize) -> Option<i16> {
+    get_u16(data, off).map(|x| x as i16)
+}
+
+/// A parsed `kern` table, format 0 subtable only (the legacy, near-universal
+/// format; formats 2/3 and Apple's `kern` version 1 aren't handled).
+pub struct Kern<'a> {
+    pairs: &'a [u8],
+    n_pairs: usize,
+}
+
+impl<'a> Kern<'a> {
+    pub fn parse(data: &'a [u8]) -> Option<Kern<'a>> {
+        let n_tables = get_u16(data, 2)?;
+        let mut pos = 4;
+        for _ in 0..n_tables {
+            let length = get_u16(data, pos + 2)? as usize;
+            let coverage = get_u16(data, pos + 4)?;
+            let format = coverage >> 8;
+            let horizontal = coverage & 1 != 0;
+            if format == 0 && horizontal {
+                let end = pos.checked_add(length)?;
+                let sub = data.get(pos..end)?;
+                let n_pairs = get_u16(sub, 6)? as usize;
+                if n_pairs.checked_mul(6)?.checked_add(14)? > sub.len() {
+                    return None;
+                }
+                return Some(Kern { pairs: &sub[14..], n_pairs: n_pairs });
+            }
+            pos = pos.checked_add(length)?;
+        }
+        None
+    }
+
+    /// Binary-searches the sorted `(left, right)` pair table, as the format
+    /// requires, packing both glyph ids into one `u32` search key.
+    pub fn lookup(&self, left: u16, right: u16) -> Option<i16> {
+        let key = ((left as u32) << 16) | right as u32;
+        let mut lo = 0;
+        let mut hi = self.n_pairs;
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            let off = mid * 6;
+            let pair_key = ((get_u16(self.pairs, off)? as u32) << 16) | get_u16(self.pairs, off + 2)? as u32;
+            if pair_key == key {
+                return get_i16(self.pairs, off + 4);
+            } else if pair_key < key {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        None
+    }
+}
+
+fn coverage_index(data: &[u8], offset: usize, glyph: u16) -> Option<usize> {
+    let cov = data.get(offset..)?;
+    match get_u16(cov, 0)? {
+        1 => {
+            let count = get_u16(cov, 2)? as usize;
+            let mut lo = 0;
+            let mut hi = count;
+            while lo < hi {
+                let mid = (lo + hi) / 2;
+                let g = get_u16(cov, 4 + mid * 2)?;
+                if g == glyph {
+                    return Some(mid);
+                } else if g < glyph {
+                    lo = mid + 1;
+                } else {
+                    hi = mid;
+                }
+            }
+            None
+        }
+        2 => {
+            let range_count = get_u16(cov, 2)? as usize;
+            for i in 0..range_count {
+                let off = 4 + i * 6;
+                let start = get_u16(cov, off)?;
+                let end = get_u16(cov, off + 2)?;
+                let start_coverage_index = get_u16(cov, off + 4)?;
+                if glyph >= start && glyph <= end {
+                    return Some((start_coverage_index + (glyph - start)) as usize);
+                }
+            }
+            None
+        }
+        _ => None,
+    }
+}
+
+/// Returns `None` if the `ClassDef` record is truncated or malformed, rather
+/// than indexing past the end of `data`; `pair_pos_lookup` then reports no
+/// adjustment for the pair. A glyph the record doesn't list is class 0.
+fn class_def_lookup(data: &[u8], offset: usize, glyph: u16) -> Option<usize> {
+    let cd = data.get(offset..)?;
+    match get_u16(cd, 0)? {
+        1 => {
+            let start = get_u16(cd, 2)?;
+            let count = get_u16(cd, 4)?;
+            if glyph >= start && glyph < start + count {
+                Some(get_u16(cd, 6 + (glyph - start) as usize * 2)? as usize)
+            } else {
+                Some(0)
+            }
+        }
+        2 => {
+            let range_count = get_u16(cd, 2)? as usize;
+            for i in 0..range_count {
+                let off = 4 + i * 6;
+                let start = get_u16(cd, off)?;
+                let end = get_u16(cd, off + 2)?;
+                if glyph >= start && glyph <= end {
+                    return Some(get_u16(cd, off + 4)? as usize);
+                }
+            }
+            Some(0)
+        }
+        _ => Some(0),
+    }
+}
+
+fn value_record_size(format: u16) -> usize {
+    format.count_ones() as usize * 2
+}
+
+/// Reads a GPOS `ValueRecord`, returning `(x_placement, y_placement,
+/// x_advance, y_advance)`; device-table offsets (hinting-only, irrelevant to
+/// this rasterizer) are skipped rather than resolved. Returns `None` if the
+/// record runs past the end of `data`.
+fn read_value_record(data: &[u8], pos: &mut usize, format: u16) -> Option<(f32, f32, f32, f32)> {
+    let mut x_placement = 0.0;
+    let mut y_placement = 0.0;
+    let mut x_advance = 0.0;
+    let mut y_advance = 0.0;
+    if format & 0x0001 != 0 {
+        x_placement = get_i16(data, *pos)? as f32;
+        *pos += 2;
+    }
+    if format & 0x0002 != 0 {
+        y_placement = get_i16(data, *pos)? as f32;
+        *pos += 2;
+    }
+    if format & 0x0004 != 0 {
+        x_advance = get_i16(data, *pos)? as f32;
+        *pos += 2;
+    }
+    if format & 0x0008 != 0 {
+        y_advance = get_i16(data, *pos)? as f32;
+        *pos += 2;
+    }
+    // XPlaDevice/YPlaDevice/XAdvDevice/YAdvDevice: each an Offset16, skipped.
+    for bit in &[0x0010u16, 0x0020, 0x0040, 0x0080] {
+        if format & bit != 0 {
+            *pos += 2;
+        }
+    }
+    Some((x_placement, y_placement, x_advance, y_advance))
+}
+
+/// `(x_advance, x_offset, y_offset)` adjustment a `ValueRecord` contributes
+/// to one glyph of a pair.
+type ValueAdjustment = (f32, f32, f32);
+
+fn pair_pos_lookup(sub: &[u8], left: u16, right: u16) -> Option<(ValueAdjustment, ValueAdjustment)> {
+    let pos_format = get_u16(sub, 0)?;
+    let coverage_offset = get_u16(sub, 2)? as usize;
+    let coverage_idx = coverage_index(sub, coverage_offset, left)?;
+    let value_format1 = get_u16(sub, 4)?;
+    let value_format2 = get_u16(sub, 6)?;
+    match pos_format {
+        1 => {
+            let pair_set_offset = get_u16(sub, 10 + coverage_idx * 2)? as usize;
+            let pair_set = sub.get(pair_set_offset..)?;
+            let pair_value_count = get_u16(pair_set, 0)? as usize;
+            let rec_size = 2 + value_record_size(value_format1) + value_record_size(value_format2);
+            let mut lo = 0;
+            let mut hi = pair_value_count;
+            while lo < hi {
+                let mid = (lo + hi) / 2;
+                let rec_off = 2 + mid * rec_size;
+                let second_glyph = get_u16(pair_set, rec_off)?;
+                if second_glyph == right {
+                    let mut p = rec_off + 2;
+                    let (xp1, yp1, xa1, _) = read_value_record(pair_set, &mut p, value_format1)?;
+                    let (xp2, yp2, xa2, _) = read_value_record(pair_set, &mut p, value_format2)?;
+                    return Some(((xa1, xp1, yp1), (xa2, xp2, yp2)));
+                } else if second_glyph < right {
+                    lo = mid + 1;
+                } else {
+                    hi = mid;
+                }
+            }
+            None
+        }
+        2 => {
+            let class_def1_offset = get_u16(sub, 8)? as usize;
+            let class_def2_offset = get_u16(sub, 10)? as usize;
+            let class1_count = get_u16(sub, 12)? as usize;
+            let class2_count = get_u16(sub, 14)? as usize;
+            let class1 = class_def_lookup(sub, class_def1_offset, left)?;
+            let class2 = class_def_lookup(sub, class_def2_offset, right)?;
+            if class1 >= class1_count || class2 >= class2_count {
+                return None;
+            }
+            let rec_size = value_record_size(value_format1) + value_record_size(value_format2);
+            let mut p = 16 + (class1 * class2_count + class2) * rec_size;
+            let (xp1, yp1, xa1, _) = read_value_record(sub, &mut p, value_format1)?;
+            let (xp2, yp2, xa2, _) = read_value_record(sub, &mut p, value_format2)?;
+            Some(((xa1, xp1, yp1), (xa2, xp2, yp2)))
+        }
+        _ => None,
+    }
+}
+
+/// A parsed `GPOS` table, reduced to its PairPos (lookup type 2) subtables;
+/// other lookup types (single/cursive/mark attachment, contextual) aren't
+/// implemented. Script/language/feature selection is skipped too -- every
+/// PairPos subtable in the lookup list is tried in order.
+pub struct Gpos<'a> {
+    pair_pos_subtables: Vec<&'a [u8]>,
+}
+
+impl<'a> Gpos<'a> {
+    pub fn parse(data: &'a [u8]) -> Option<Gpos<'a>> {
+        let lookup_list_offset = get_u16(data, 8)? as usize;
+        let lookup_list = data.get(lookup_list_offset..)?;
+        let lookup_count = get_u16(lookup_list, 0)? as usize;
+        let mut pair_pos_subtables = Vec::new();
+        for i in 0..lookup_count {
+            let lookup_offset = get_u16(lookup_list, 2 + i * 2)? as usize;
+            let lookup = lookup_list.get(lookup_offset..)?;
+            if get_u16(lookup, 0)? != 2 {
+                continue;
+            }
+            let sub_table_count = get_u16(lookup, 4)? as usize;
+            for j in 0..sub_table_count {
+                let sub_offset = get_u16(lookup, 6 + j * 2)? as usize;
+                pair_pos_subtables.push(lookup.get(sub_offset..)?);
+            }
+        }
+        Some(Gpos { pair_pos_subtables: pair_pos_subtables })
+    }
+
+    pub fn lookup(&self, left: u16, right: u16) -> Option<(ValueAdjustment, ValueAdjustment)> {
+        self.pair_pos_subtables.iter().filter_map(|sub| pair_pos_lookup(sub, left, right)).next()
+    }
+}
+
+/// Per-glyph positioning adjustment, added on top of the glyph's own
+/// `Hmtx` advance when laying out `glyphs` as a run.
+#[derive(Clone, Copy, Default)]
+pub struct GlyphAdjustment {
+    pub x_advance: f32,
+    pub x_offset: f32,
+    pub y_offset: f32,
+}
+
+/// Computes per-glyph adjustments for a glyph-id run, preferring GPOS
+/// PairPos over `kern` for any pair both cover.
+pub fn adjustments(gpos: Option<&Gpos>, kern: Option<&Kern>, glyphs: &[u16]) -> Vec<GlyphAdjustment> {
+    let mut out = vec![GlyphAdjustment::default(); glyphs.len()];
+    for i in 0..glyphs.len().saturating_sub(1) {
+        let (left, right) = (glyphs[i], glyphs[i + 1]);
+        if let Some(gpos) = gpos {
+            if let Some((v1, v2)) = gpos.lookup(left, right) {
+                out[i].x_advance += v1.0;
+                out[i].x_offset += v1.1;
+                out[i].y_offset += v1.2;
+                out[i + 1].x_advance += v2.0;
+                out[i + 1].x_offset += v2.1;
+                out[i + 1].y_offset += v2.2;
+                continue;
+            }
+        }
+        if let Some(kern) = kern {
+            if let Some(value) = kern.lookup(left, right) {
+                out[i].x_advance += value as f32;
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build_kern_format0_two_pairs() -> Vec<u8> {
+        let mut sub = vec![];
+        sub.extend_from_slice(&0u16.to_be_bytes()); // subtable version
+        sub.extend_from_slice(&0u16.to_be_bytes()); // length placeholder
+        sub.extend_from_slice(&0x0001u16.to_be_bytes()); // coverage: format 0, horizontal
+        sub.extend_from_slice(&2u16.to_be_bytes()); // nPairs
+        sub.extend_from_slice(&0u16.to_be_bytes()); // searchRange
+        sub.extend_from_slice(&0u16.to_be_bytes()); // entrySelector
+        sub.extend_from_slice(&0u16.to_be_bytes()); // rangeShift
+        // pairs, sorted ascending by (left << 16 | right)
+        sub.extend_from_slice(&5u16.to_be_bytes());
+        sub.extend_from_slice(&7u16.to_be_bytes());
+        sub.extend_from_slice(&100i16.to_be_bytes());
+        sub.extend_from_slice(&7u16.to_be_bytes());
+        sub.extend_from_slice(&9u16.to_be_bytes());
+        sub.extend_from_slice(&(-30i16).to_be_bytes());
+        let len = sub.len() as u16;
+        sub[2..4].copy_from_slice(&len.to_be_bytes());
+
+        let mut data = vec![];
+        data.extend_from_slice(&0u16.to_be_bytes()); // version
+        data.extend_from_slice(&1u16.to_be_bytes()); // nTables
+        data.extend_from_slice(&sub);
+        data
+    }
+
+    fn build_pair_pos_format1() -> Vec<u8> {
+        // PosFormat1 subtable: coverage of just glyph 5, one PairSet with a
+        // single (right=7, xAdvance=80) record (valueFormat2 is empty).
+        let mut coverage = vec![];
+        coverage.extend_from_slice(&1u16.to_be_bytes()); // format 1
+        coverage.extend_from_slice(&1u16.to_be_bytes()); // glyphCount
+        coverage.extend_from_slice(&5u16.to_be_bytes());
+
+        let mut pair_set = vec![];
+        pair_set.extend_from_slice(&1u16.to_be_bytes()); // pairValueCount
+        pair_set.extend_from_slice(&7u16.to_be_bytes()); // secondGlyph
+        pair_set.extend_from_slice(&80i16.to_be_bytes()); // xAdvance
+
+        let coverage_offset = 12; // posFormat+coverageOffset+vf1+vf2+pairSetCount+1 offset slot
+        let pair_set_offset = coverage_offset + coverage.len();
+
+        let mut sub = vec![];
+        sub.extend_from_slice(&1u16.to_be_bytes()); // PosFormat
+        sub.extend_from_slice(&(coverage_offset as u16).to_be_bytes());
+        sub.extend_from_slice(&0x0004u16.to_be_bytes()); // valueFormat1: XAdvance
+        sub.extend_from_slice(&0u16.to_be_bytes()); // valueFormat2: none
+        sub.extend_from_slice(&1u16.to_be_bytes()); // pairSetCount
+        sub.extend_from_slice(&(pair_set_offset as u16).to_be_bytes());
+        sub.extend_from_slice(&coverage);
+        sub.extend_from_slice(&pair_set);
+        sub
+    }
+
+    #[test]
+    fn kern_format0_lookup() {
+        let data = build_kern_format0_two_pairs();
+        let kern = Kern::parse(&data).unwrap();
+        assert_eq!(kern.lookup(5, 7), Some(100));
+        assert_eq!(kern.lookup(7, 9), Some(-30));
+        assert_eq!(kern.lookup(5, 9), None);
+    }
+
+    #[test]
+    fn coverage_index_format1_lookup() {
+        let mut data = vec![];
+        data.extend_from_slice(&1u16.to_be_bytes()); // format
+        data.extend_from_slice(&3u16.to_be_bytes()); // glyphCount
+        for g in [5u16, 7, 9].iter() {
+            data.extend_from_slice(&g.to_be_bytes());
+        }
+        assert_eq!(coverage_index(&data, 0, 7), Some(1));
+        assert_eq!(coverage_index(&data, 0, 10), None);
+    }
+
+    #[test]
+    fn coverage_index_format2_lookup() {
+        let mut data = vec![];
+        data.extend_from_slice(&2u16.to_be_bytes()); // format
+        data.extend_from_slice(&1u16.to_be_bytes()); // rangeCount
+        data.extend_from_slice(&10u16.to_be_bytes()); // startGlyph
+        data.extend_from_slice(&20u16.to_be_bytes()); // endGlyph
+        data.extend_from_slice(&0u16.to_be_bytes()); // startCoverageIndex
+        assert_eq!(coverage_index(&data, 0, 15), Some(5));
+        assert_eq!(coverage_index(&data, 0, 25), None);
+    }
+
+    #[test]
+    fn class_def_lookup_format1_lookup() {
+        let mut data = vec![];
+        data.extend_from_slice(&1u16.to_be_bytes()); // format
+        data.extend_from_slice(&10u16.to_be_bytes()); // startGlyph
+        data.extend_from_slice(&3u16.to_be_bytes()); // glyphCount
+        for c in [0u16, 1, 2].iter() {
+            data.extend_from_slice(&c.to_be_bytes());
+        }
+        assert_eq!(class_def_lookup(&data, 0, 11), Some(1));
+        assert_eq!(class_def_lookup(&data, 0, 99), Some(0)); // outside range -> class 0
+    }
+
+    #[test]
+    fn class_def_lookup_format2_lookup() {
+        let mut data = vec![];
+        data.extend_from_slice(&2u16.to_be_bytes()); // format
+        data.extend_from_slice(&1u16.to_be_bytes()); // rangeCount
+        data.extend_from_slice(&10u16.to_be_bytes()); // startGlyph
+        data.extend_from_slice(&20u16.to_be_bytes()); // endGlyph
+        data.extend_from_slice(&3u16.to_be_bytes()); // class
+        assert_eq!(class_def_lookup(&data, 0, 15), Some(3));
+        assert_eq!(class_def_lookup(&data, 0, 25), Some(0));
+    }
+
+    #[test]
+    fn pair_pos_format1_lookup() {
+        let sub = build_pair_pos_format1();
+        let ((xa1, xp1, yp1), (xa2, xp2, yp2)) = pair_pos_lookup(&sub, 5, 7).unwrap();
+        assert_eq!(xa1, 80.0);
+        assert_eq!((xp1, yp1), (0.0, 0.0));
+        assert_eq!((xa2, xp2, yp2), (0.0, 0.0, 0.0));
+        assert_eq!(pair_pos_lookup(&sub, 5, 8), None);
+    }
+
+    #[test]
+    fn adjustments_prefers_gpos_over_kern() {
+        let pair_pos = build_pair_pos_format1();
+        let gpos = Gpos { pair_pos_subtables: vec![&pair_pos] };
+        let kern_data = build_kern_format0_two_pairs();
+        let kern = Kern::parse(&kern_data).unwrap();
+
+        let glyphs = [5u16, 7, 9];
+        let adj = adjustments(Some(&gpos), Some(&kern), &glyphs);
+        // (5, 7) is covered by the GPOS subtable -- it should win over kern's 100.
+        assert_eq!(adj[0].x_advance, 80.0);
+        // (7, 9) isn't in the GPOS coverage (only glyph 5 is), so it falls
+        // back to kern's value.
+        assert_eq!(adj[1].x_advance, -30.0);
+    }
+
+    #[test]
+    fn kern_parse_on_truncated_data_does_not_panic() {
+        // Header claims one subtable but the table ends right after it.
+        let mut data = vec![];
+        data.extend_from_slice(&0u16.to_be_bytes()); // version
+        data.extend_from_slice(&1u16.to_be_bytes()); // nTables
+        assert!(Kern::parse(&data).is_none());
+
+        // Subtable header present, but nPairs overruns the subtable's own
+        // declared length.
+        let mut sub = vec![];
+        sub.extend_from_slice(&0u16.to_be_bytes()); // subtable version
+        sub.extend_from_slice(&0u16.to_be_bytes()); // length placeholder
+        sub.extend_from_slice(&0x0001u16.to_be_bytes()); // coverage: format 0, horizontal
+        sub.extend_from_slice(&0xffffu16.to_be_bytes()); // nPairs (bogus)
+        sub.extend_from_slice(&[0u8; 6]); // searchRange/entrySelector/rangeShift
+        let len = sub.len() as u16;
+        sub[2..4].copy_from_slice(&len.to_be_bytes());
+        let mut data = vec![];
+        data.extend_from_slice(&0u16.to_be_bytes());
+        data.extend_from_slice(&1u16.to_be_bytes());
+        data.extend_from_slice(&sub);
+        assert!(Kern::parse(&data).is_none());
+    }
+
+    #[test]
+    fn coverage_index_on_truncated_data_does_not_panic() {
+        let data = [0u8, 1, 0, 3]; // format 1, glyphCount=3, but no glyph ids follow
+        assert_eq!(coverage_index(&data, 0, 7), None);
+    }
+
+    #[test]
+    fn class_def_lookup_on_truncated_data_does_not_panic() {
+        let data = [0u8, 1, 0, 10, 0, 3]; // format 1, startGlyph=10, glyphCount=3, no classes
+        assert_eq!(class_def_lookup(&data, 0, 11), None);
+    }
+
+    #[test]
+    fn pair_pos_lookup_on_truncated_data_does_not_panic() {
+        // A well-formed header pointing at a PairSet that doesn't exist.
+        let mut sub = vec![];
+        sub.extend_from_slice(&1u16.to_be_bytes()); // PosFormat
+        sub.extend_from_slice(&12u16.to_be_bytes()); // coverageOffset
+        sub.extend_from_slice(&0x0004u16.to_be_bytes()); // valueFormat1
+        sub.extend_from_slice(&0u16.to_be_bytes()); // valueFormat2
+        sub.extend_from_slice(&1u16.to_be_bytes()); // pairSetCount
+        sub.extend_from_slice(&9999u16.to_be_bytes()); // pairSetOffset (out of range)
+        sub.extend_from_slice(&1u16.to_be_bytes()); // coverage format 1
+        sub.extend_from_slice(&1u16.to_be_bytes()); // glyphCount
+        sub.extend_from_slice(&5u16.to_be_bytes());
+        assert_eq!(pair_pos_lookup(&sub, 5, 7), None);
+    }
+
+    #[test]
+    fn gpos_parse_on_truncated_data_does_not_panic() {
+        assert!(Gpos::parse(&[0u8; 8]).is_none());
+        let mut data = vec![0u8; 10];
+        data[8..10].copy_from_slice(&9999u16.to_be_bytes()); // lookupListOffset (out of range)
+        assert!(Gpos::parse(&data).is_none());
+    }
+}
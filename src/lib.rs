@@ -0,0 +1,37 @@
+// Copyright 2015 Google Inc. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! font-rs is a library for rasterizing glyphs from TrueType and OpenType fonts.
+
+// Only enables the nightly `portable_simd` feature when built with
+// `--features portable-simd`; a plain build never hits this, same as the
+// hand-written x86 intrinsics in `accumulate` staying inert without
+// `--features sse`.
+#![cfg_attr(feature = "portable-simd", feature(portable_simd))]
+
+extern crate rustybuzz;
+
+#[macro_use]
+mod macros;
+
+pub mod accumulate;
+pub mod atlas;
+pub mod cache;
+pub mod cff;
+pub mod geom;
+pub mod kerning;
+pub mod raster;
+pub mod font;
+pub mod shape;
+pub mod variations;
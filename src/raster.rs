@@ -16,7 +16,9 @@
 
 use std::cmp::min;
 
-use geom::Point;
+#[cfg(not(feature="sse"))]
+use accumulate;
+use geom::{affine_pt, Affine, Point};
 
 // TODO: sort out crate structure. Right now we want this when compiling raster as a binary,
 // but need it commented out when compiling showttf
@@ -25,7 +27,20 @@ use geom::Point;
 pub struct Raster {
     w: usize,
     h: usize,
-    a: Vec<f32>
+    a: Vec<f32>,
+    /// Horizontal oversampling factor: 1 for a normal single-channel raster,
+    /// 3 for one built with `try_new_subpixel` (one accumulation column per
+    /// LCD subpixel stripe). `draw_line`/`draw_quad`/`draw_cubic` scale
+    /// incoming x coordinates by this so callers always work in logical
+    /// pixel space; only `get_subpixel_bitmap` needs to know it's there.
+    subpixel_scale: usize,
+    /// Current affine transform, applied to every point passed to
+    /// `draw_line`/`draw_quad`/`draw_cubic` before accumulation (and before
+    /// the `subpixel_scale` stretch, so it stays in logical device space).
+    /// Defaults to the identity transform.
+    transform: Affine,
+    /// Transforms saved by `push_transform`, restored by `pop_transform`.
+    transform_stack: Vec<Affine>,
 }
 
 #[cfg(feature="sse")]
@@ -39,13 +54,110 @@ fn recip(x: f32) -> f32 {
     x.recip()
 }
 
+/// Errors that can occur while rasterizing instead of panicking.
+#[derive(Debug, PartialEq)]
+pub enum RasterError {
+    /// The requested buffer dimensions overflow when computing `w * h`.
+    DimensionOverflow,
+    /// A point passed to a `draw_*` call has a NaN or infinite coordinate.
+    InvalidCoordinate,
+}
+
+fn point_is_finite(p: &Point) -> bool {
+    p.x.is_finite() && p.y.is_finite()
+}
+
 impl Raster {
+    pub fn try_new(w: usize, h: usize) -> Result<Raster, RasterError> {
+        let len = w.checked_mul(h)
+            .and_then(|n| n.checked_add(4))
+            .ok_or(RasterError::DimensionOverflow)?;
+        Ok(Raster {
+            w: w,
+            h: h,
+            a: vec!(0.0; len),
+            subpixel_scale: 1,
+            transform: Affine::identity(),
+            transform_stack: Vec::new(),
+        })
+    }
+
     pub fn new(w: usize, h: usize) -> Raster {
-        Raster{ w: w, h: h, a: vec!(0.0; w * h + 4) }
+        Raster::try_new(w, h).expect("Raster dimensions overflow")
+    }
+
+    /// Like `try_new`, but every point passed to a `draw_*` call is mapped
+    /// through `transform` first -- useful for synthetic obliquing,
+    /// rotation, or sub-pixel positioning without pre-transforming the
+    /// outline yourself.
+    pub fn with_transform(w: usize, h: usize, transform: Affine) -> Result<Raster, RasterError> {
+        let mut r = Raster::try_new(w, h)?;
+        r.transform = transform;
+        Ok(r)
+    }
+
+    /// Like `try_new`, but builds a raster three columns wide per logical
+    /// pixel -- one accumulation column per LCD subpixel stripe. Draw calls
+    /// take the same logical-pixel coordinates as always; only
+    /// `get_subpixel_bitmap` needs the extra resolution.
+    pub fn try_new_subpixel(w: usize, h: usize) -> Result<Raster, RasterError> {
+        let scaled_w = w.checked_mul(3).ok_or(RasterError::DimensionOverflow)?;
+        let mut r = Raster::try_new(scaled_w, h)?;
+        r.subpixel_scale = 3;
+        Ok(r)
+    }
+
+    /// Replaces the current transform outright, discarding the push/pop
+    /// stack's notion of what it was nested under (the stack itself is
+    /// untouched, so a later `pop_transform` still restores whatever was
+    /// pushed before this call).
+    pub fn set_transform(&mut self, transform: Affine) {
+        self.transform = transform;
+    }
+
+    /// Saves the current transform and composes `transform` on top of it,
+    /// so points are mapped through `transform` first and the previous
+    /// transform second.
+    pub fn push_transform(&mut self, transform: Affine) {
+        self.transform_stack.push(self.transform);
+        self.transform = Affine::concat(&self.transform, &transform);
+    }
+
+    /// Restores the transform saved by the matching `push_transform`. A
+    /// `pop_transform` with no matching push is a no-op.
+    pub fn pop_transform(&mut self) {
+        if let Some(transform) = self.transform_stack.pop() {
+            self.transform = transform;
+        }
+    }
+
+    pub fn try_draw_line(&mut self, p0: &Point, p1: &Point) -> Result<(), RasterError> {
+        if !point_is_finite(p0) || !point_is_finite(p1) {
+            return Err(RasterError::InvalidCoordinate);
+        }
+        self.draw_line(p0, p1);
+        Ok(())
+    }
+
+    pub fn try_draw_quad(&mut self, p0: &Point, p1: &Point, p2: &Point) -> Result<(), RasterError> {
+        if !point_is_finite(p0) || !point_is_finite(p1) || !point_is_finite(p2) {
+            return Err(RasterError::InvalidCoordinate);
+        }
+        self.draw_quad(p0, p1, p2);
+        Ok(())
     }
 
     pub fn draw_line(&mut self, p0: &Point, p1: &Point) {
         //println!("draw_line {} {}", p0, p1);
+        let (p0, p1) = (affine_pt(&self.transform, p0), affine_pt(&self.transform, p1));
+        let (p0, p1) = (&p0, &p1);
+        let scale = self.subpixel_scale as f32;
+        let (p0, p1) = if scale == 1.0 {
+            (*p0, *p1)
+        } else {
+            (Point { x: p0.x * scale, y: p0.y }, Point { x: p1.x * scale, y: p1.y })
+        };
+        let (p0, p1) = (&p0, &p1);
         if p0.y == p1.y {
             return
         }
@@ -56,43 +168,64 @@ impl Raster {
         };
         let dxdy = (p1.x - p0.x) / (p1.y - p0.y);
         let mut x = p0.x;
-        let y0 = p0.y as usize;  // note: implicit max of 0 because usize (TODO: really true?)
         if p0.y < 0.0 {
             x -= p0.y * dxdy;
         }
-        for y in y0..min(self.h, p1.y.ceil() as usize) {
+        let y0 = p0.y.max(0.0) as usize;
+        let y1 = min(self.h, p1.y.ceil().max(0.0) as usize);
+        let wf = self.w as f32;
+        for y in y0..y1 {
             let linestart = y * self.w;
             let dy = ((y + 1) as f32).min(p1.y) - (y as f32).max(p0.y);
             let xnext = x + dxdy * dy;
             let d = dy * dir;
+            // Clip the segment's x extent to the buffer before touching the
+            // accumulation array; an outline can legitimately stray outside the
+            // computed bbox by a fraction of a pixel (see `draw_line_index_panic`).
             let (x0, x1) = if x < xnext { (x, xnext) } else { (xnext, x) };
-            let x0floor = x0.floor();
-            let x0i = x0floor as i32;
-            let x1ceil = x1.ceil();
-            let x1i = x1ceil as i32;
-            if x1i <= x0i + 1 {
-                let xmf = 0.5 * (x + xnext) - x0floor;
-                self.a[linestart + x0i as usize] += d - d * xmf;
-                self.a[linestart + (x0i + 1) as usize] += d * xmf;
+            if x1 <= 0.0 {
+                // The whole span lies to the left of the buffer: every pixel in
+                // this row (and, via the prefix sum in `get_bitmap`, every pixel
+                // to the right of it) is fully covered by this edge.
+                self.a[linestart] += d;
+            } else if x0 >= wf {
+                // The whole span lies to the right of the buffer: it covers
+                // nothing we can see.
             } else {
-                let s = recip(x1 - x0);
-                let x0f = x0 - x0floor;
-                let a0 = 0.5 * s * (1.0 - x0f) * (1.0 - x0f);
-                let x1f = x1 - x1ceil + 1.0;
-                let am = 0.5 * s * x1f * x1f;
-                self.a[linestart + x0i as usize] += d * a0;
-                if x1i == x0i + 2 {
-                    self.a[linestart + (x0i + 1) as usize] += d * (1.0 - a0 - am);
+                let x = x.max(0.0).min(wf);
+                let xnext = xnext.max(0.0).min(wf);
+                let x0 = x0.max(0.0);
+                let x1 = x1.min(wf);
+                let x0floor = x0.floor();
+                let x0i = x0floor as i32;
+                let x1ceil = x1.ceil();
+                // Guard against the index ever reaching past the row: x1ceil is
+                // at most wf, but floating point rounding can still tip it over.
+                let x1i = min(x1ceil as usize, self.w) as i32;
+                if x1i <= x0i + 1 {
+                    let xmf = 0.5 * (x + xnext) - x0floor;
+                    self.a[linestart + x0i as usize] += d - d * xmf;
+                    self.a[linestart + (x0i + 1) as usize] += d * xmf;
                 } else {
-                    let a1 = s * (1.5 - x0f);
-                    self.a[linestart + (x0i + 1) as usize] += d * (a1 - a0);
-                    for xi in x0i + 2 .. x1i - 1 {
-                        self.a[linestart + xi as usize] += d * s;
+                    let s = recip(x1 - x0);
+                    let x0f = x0 - x0floor;
+                    let a0 = 0.5 * s * (1.0 - x0f) * (1.0 - x0f);
+                    let x1f = x1 - x1ceil + 1.0;
+                    let am = 0.5 * s * x1f * x1f;
+                    self.a[linestart + x0i as usize] += d * a0;
+                    if x1i == x0i + 2 {
+                        self.a[linestart + (x0i + 1) as usize] += d * (1.0 - a0 - am);
+                    } else {
+                        let a1 = s * (1.5 - x0f);
+                        self.a[linestart + (x0i + 1) as usize] += d * (a1 - a0);
+                        for xi in x0i + 2 .. x1i - 1 {
+                            self.a[linestart + xi as usize] += d * s;
+                        }
+                        let a2 = a1 + (x1i - x0i - 3) as f32 * s;
+                        self.a[linestart + (x1i - 1) as usize] += d * (1.0 - a2 - am);
                     }
-                    let a2 = a1 + (x1i - x0i - 3) as f32 * s;
-                    self.a[linestart + (x1i - 1) as usize] += d * (1.0 - a2 - am);
+                    self.a[linestart + x1i as usize] += d * am;
                 }
-                self.a[linestart + x1i as usize] += d * am;
             }
             x = xnext;
         }
@@ -122,6 +255,40 @@ impl Raster {
         self.draw_line(&p, p2);
     }
 
+    /// Flattens a cubic Bézier to line segments using the same
+    /// deviation-from-chord heuristic as `draw_quad`, comparing the control
+    /// points against the cubic's linear (one-third/two-thirds) baseline.
+    /// This is what lets CFF/Type2 outlines (true cubics) share the same
+    /// rasterizer as TrueType's quadratic `glyf` outlines.
+    pub fn draw_cubic(&mut self, p0: &Point, p1: &Point, p2: &Point, p3: &Point) {
+        let d1x = p1.x - (p0.x + (p3.x - p0.x) * (1.0 / 3.0));
+        let d1y = p1.y - (p0.y + (p3.y - p0.y) * (1.0 / 3.0));
+        let d2x = p2.x - (p0.x + (p3.x - p0.x) * (2.0 / 3.0));
+        let d2y = p2.y - (p0.y + (p3.y - p0.y) * (2.0 / 3.0));
+        let devsq = (d1x * d1x + d1y * d1y).max(d2x * d2x + d2y * d2y);
+        if devsq < 0.333 {
+            self.draw_line(p0, p3);
+            return
+        }
+        let tol = 3.0;
+        let n = 1 + (tol * devsq).sqrt().sqrt().floor() as usize;
+        let mut p = *p0;
+        let nrecip = recip(n as f32);
+        let mut t = 0.0;
+        for _i in 0 .. n - 1 {
+            t += nrecip;
+            let a = Point::lerp(t, p0, p1);
+            let b = Point::lerp(t, p1, p2);
+            let c = Point::lerp(t, p2, p3);
+            let ab = Point::lerp(t, &a, &b);
+            let bc = Point::lerp(t, &b, &c);
+            let pn = Point::lerp(t, &ab, &bc);
+            self.draw_line(&p, &pn);
+            p = pn;
+        }
+        self.draw_line(&p, p3);
+    }
+
 /*
     fn get_bitmap_fancy(&self) -> Vec<u8> {
         let mut acc = 0.0;
@@ -146,15 +313,75 @@ impl Raster {
         r
     }
 
+    // `accumulate_auto` probes the host CPU at runtime (falling back to the
+    // scalar loop when nothing vectorized matches, or always when the
+    // `portable-simd` feature isn't enabled at all), so this one path covers
+    // both the portable-simd and plain-scalar cases safely.
     #[cfg(not(feature="sse"))]
     pub fn get_bitmap(&self) -> Vec<u8> {
-        let mut acc = 0.0;
-        (0..self.w * self.h).map(|i| {
-        // This would translate really well to SIMD
-            acc += self.a[i];
-            let y = acc.abs();
-            let y = if y < 1.0 { y } else { 1.0 };
-            (255.0 * y) as u8
-        }).collect()
+        accumulate::accumulate_auto(&self.a[0..self.w * self.h])
+    }
+
+    /// Renders an LCD-subpixel-filtered RGB (or `bgr` order) bitmap from a
+    /// raster built with `try_new_subpixel`. Coverage is derived per
+    /// subpixel column via the same running prefix sum `get_bitmap` uses,
+    /// then each output channel is its own 5-tap `[1, 2, 3, 2, 1]` weighted
+    /// average of the subpixel columns centered on *that channel's own*
+    /// stripe (R, G, then B), which softens the color fringing plain 3x
+    /// coverage sampling would otherwise show on near-vertical edges. Each
+    /// channel is normalized by the weight actually collected rather than
+    /// the fixed total of 9, so columns clipped at the bitmap edge don't
+    /// come out darker than they should.
+    ///
+    /// Panics (via the `assert_eq!` below) if called on a raster that
+    /// wasn't built with `try_new_subpixel`.
+    pub fn get_subpixel_bitmap(&self, bgr: bool) -> Vec<u8> {
+        assert_eq!(self.subpixel_scale, 3);
+        let cols = self.w;
+        let logical_w = cols / 3;
+        let weights = [1.0f32, 2.0, 3.0, 2.0, 1.0];
+        let mut out = Vec::with_capacity(logical_w * self.h * 3);
+        for y in 0..self.h {
+            let row = &self.a[y * self.w .. y * self.w + cols];
+            let mut acc = 0.0;
+            let mut coverage = Vec::with_capacity(cols);
+            for &a in row {
+                acc += a;
+                let c = acc.abs();
+                coverage.push(if c < 1.0 { c } else { 1.0 });
+            }
+            for px in 0..logical_w {
+                let mut channel = [0.0f32; 3];
+                for (stripe, out_c) in channel.iter_mut().enumerate() {
+                    let center = px * 3 + stripe;
+                    let mut acc = 0.0;
+                    let mut wsum = 0.0;
+                    for (tap, &weight) in weights.iter().enumerate() {
+                        let offset = tap as isize - 2;
+                        let col = center as isize + offset;
+                        if col < 0 || col >= cols as isize {
+                            continue
+                        }
+                        acc += coverage[col as usize] * weight;
+                        wsum += weight;
+                    }
+                    *out_c = if wsum > 0.0 { acc / wsum } else { 0.0 };
+                }
+                let to_byte = |c: f32| (255.0 * c.min(1.0)) as u8;
+                let (r, g, b) = (to_byte(channel[0]), to_byte(channel[1]), to_byte(channel[2]));
+                if bgr {
+                    out.push(b); out.push(g); out.push(r);
+                } else {
+                    out.push(r); out.push(g); out.push(b);
+                }
+            }
+        }
+        out
+    }
+
+    /// Alias for `get_subpixel_bitmap`, named to match callers looking for
+    /// an LCD-specific entry point alongside the plain `get_bitmap`.
+    pub fn get_bitmap_lcd(&self, bgr: bool) -> Vec<u8> {
+        self.get_subpixel_bitmap(bgr)
     }
 }
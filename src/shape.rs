@@ -0,0 +1,113 @@
+// Copyright 2020 Google Inc. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Text shaping and run layout.
+//!
+//! font-rs only knows how to rasterize individual glyph outlines; this
+//! module bridges the gap from a plain UTF-8 string to laid-out, positioned
+//! glyphs by delegating script-aware shaping (kerning, ligatures, complex
+//! scripts) to `rustybuzz`, then rasterizing the resulting glyph run with
+//! the existing outline pipeline.
+
+use rustybuzz;
+
+use font::{Font, GlyphBitmap};
+
+/// One shaped glyph, positioned relative to the start of the run in pixels.
+pub struct PositionedGlyph {
+    pub glyph_id: u16,
+    pub x: f32,
+    pub y: f32,
+}
+
+/// Shapes `text` with `face` at `size` pixels, returning one positioned
+/// entry per glyph in the shaped output (a cluster may yield several), in
+/// visual (left-to-right pen) order.
+pub fn shape_text(face: &rustybuzz::Face, text: &str, size: u32) -> Vec<PositionedGlyph> {
+    let mut buffer = rustybuzz::UnicodeBuffer::new();
+    buffer.push_str(text);
+    let glyph_buffer = rustybuzz::shape(face, &[], buffer);
+    let scale = size as f32 / face.units_per_em() as f32;
+    let infos = glyph_buffer.glyph_infos();
+    let positions = glyph_buffer.glyph_positions();
+    let mut glyphs = Vec::with_capacity(infos.len());
+    let mut pen_x = 0.0;
+    let mut pen_y = 0.0;
+    for (info, pos) in infos.iter().zip(positions.iter()) {
+        glyphs.push(PositionedGlyph {
+            glyph_id: info.glyph_id as u16,
+            x: pen_x + pos.x_offset as f32 * scale,
+            y: pen_y + pos.y_offset as f32 * scale,
+        });
+        pen_x += pos.x_advance as f32 * scale;
+        pen_y += pos.y_advance as f32 * scale;
+    }
+    glyphs
+}
+
+/// Shapes `text` and rasterizes the whole run into a single coverage
+/// bitmap sized to fit every glyph, so callers get one canvas for a word
+/// or line instead of stitching together individual glyph bitmaps.
+pub fn render_text(
+    font: &Font, face: &rustybuzz::Face, text: &str, size: u32,
+) -> Option<GlyphBitmap> {
+    let placed: Vec<(f32, f32, GlyphBitmap)> = shape_text(face, text, size)
+        .into_iter()
+        .filter_map(|g| font.render_glyph(g.glyph_id, size).map(|bmp| (g.x, g.y, bmp)))
+        .collect();
+    if placed.is_empty() {
+        return None;
+    }
+    let mut min_l = i32::max_value();
+    let mut min_t = i32::max_value();
+    let mut max_r = i32::min_value();
+    let mut max_b = i32::min_value();
+    for &(x, y, ref bmp) in &placed {
+        let l = x.round() as i32 + bmp.left;
+        let t = y.round() as i32 + bmp.top;
+        min_l = min_l.min(l);
+        min_t = min_t.min(t);
+        max_r = max_r.max(l + bmp.width as i32);
+        max_b = max_b.max(t + bmp.height as i32);
+    }
+    let width = (max_r - min_l).max(0) as usize;
+    let height = (max_b - min_t).max(0) as usize;
+    let mut canvas = vec![0u8; width * height];
+    for &(x, y, ref bmp) in &placed {
+        let ox = x.round() as i32 + bmp.left - min_l;
+        let oy = y.round() as i32 + bmp.top - min_t;
+        for row in 0..bmp.height {
+            for col in 0..bmp.width {
+                let v = bmp.data[row * bmp.width + col];
+                if v == 0 {
+                    continue;
+                }
+                let cx = ox + col as i32;
+                let cy = oy + row as i32;
+                if cx < 0 || cy < 0 || cx as usize >= width || cy as usize >= height {
+                    continue;
+                }
+                let idx = cy as usize * width + cx as usize;
+                canvas[idx] = canvas[idx].max(v);
+            }
+        }
+    }
+    Some(GlyphBitmap {
+        width: width,
+        height: height,
+        left: min_l,
+        top: min_t,
+        data: canvas,
+    })
+}
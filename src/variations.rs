@@ -0,0 +1,689 @@
+// Copyright 2021 Google Inc. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! OpenType Font Variations (`fvar`/`avar`/`gvar`), enough to turn a set of
+//! user-facing axis coordinates (weight, width, ...) into per-point deltas
+//! for a `glyf` `SimpleGlyph`, including IUP (Inferred Unreferenced Point)
+//! interpolation for points a tuple doesn't specify deltas for.
+
+// Bounds-checked reads, mirroring `font.rs`'s `get_u16`/`get_u32`: a
+// truncated or malformed `fvar`/`avar`/`gvar` table must fail gracefully
+// instead of indexing past the end of the slice.
+fn get_u16(data: &[u8], off: usize) -> Option<u16> {
+    if off + 2 > data.len() {
+        None
+    } else {
+        Some(((data[off] as u16) << 8) | data[off + 1] as u16)
+    }
+}
+
+fn get_i16(data: &[u8], off: usize) -> Option<i16> {
+    get_u16(data, off).map(|x| x as i16)
+}
+
+fn get_u32(data: &[u8], off: usize) -> Option<u32> {
+    if off + 4 > data.len() {
+        None
+    } else {
+        Some(
+            ((data[off] as u32) << 24)
+                | ((data[off + 1] as u32) << 16)
+                | ((data[off + 2] as u32) << 8)
+                | data[off + 3] as u32,
+        )
+    }
+}
+
+fn get_f2_14(data: &[u8], off: usize) -> Option<f32> {
+    get_i16(data, off).map(|x| x as f32 / 16384.0)
+}
+
+fn get_fixed(data: &[u8], off: usize) -> Option<f32> {
+    get_u32(data, off).map(|x| (x as i32) as f32 / 65536.0)
+}
+
+/// A parsed `fvar` table: the variation axes and their min/default/max
+/// range, in the order user coordinates are expected to be supplied in.
+pub struct Fvar<'a> {
+    data: &'a [u8],
+    axes_offset: usize,
+    axis_count: usize,
+}
+
+impl<'a> Fvar<'a> {
+    pub fn parse(data: &'a [u8]) -> Option<Fvar<'a>> {
+        if data.len() < 16 {
+            return None;
+        }
+        let axes_offset = get_u16(data, 4)? as usize;
+        let axis_count = get_u16(data, 8)? as usize;
+        Some(Fvar { data: data, axes_offset: axes_offset, axis_count: axis_count })
+    }
+
+    pub fn axis_count(&self) -> usize {
+        self.axis_count
+    }
+
+    /// Returns `None` if `i` is out of range or the axis record it points at
+    /// runs past the end of the table (a truncated `fvar`).
+    fn axis(&self, i: usize) -> Option<(f32, f32, f32)> {
+        let off = self.axes_offset.checked_add(i.checked_mul(20)?)?;
+        let min = get_fixed(self.data, off + 4)?;
+        let default = get_fixed(self.data, off + 8)?;
+        let max = get_fixed(self.data, off + 12)?;
+        Some((min, default, max))
+    }
+
+    /// Normalizes a user-space axis value to `[-1, 1]` against that axis's
+    /// min/default/max, per the OpenType variations spec. Returns `0.0` (no
+    /// variation) if the axis record can't be read.
+    pub fn normalize(&self, i: usize, user_value: f32) -> f32 {
+        let (min, default, max) = match self.axis(i) {
+            Some(v) => v,
+            None => return 0.0,
+        };
+        if user_value < default {
+            if min == default {
+                0.0
+            } else {
+                (user_value.max(min) - default) / (default - min)
+            }
+        } else if user_value > default {
+            if max == default {
+                0.0
+            } else {
+                (user_value.min(max) - default) / (max - default)
+            }
+        } else {
+            0.0
+        }
+    }
+}
+
+/// A parsed `avar` table: a piecewise-linear remapping applied to each
+/// axis's already fvar-normalized `[-1, 1]` coordinate.
+pub struct Avar {
+    segment_maps: Vec<Vec<(f32, f32)>>,
+}
+
+impl Avar {
+    pub fn parse(data: &[u8]) -> Option<Avar> {
+        if data.len() < 8 {
+            return None;
+        }
+        let axis_count = get_u16(data, 6)? as usize;
+        let mut pos = 8;
+        let mut segment_maps = Vec::with_capacity(axis_count);
+        for _ in 0..axis_count {
+            let pair_count = get_u16(data, pos)? as usize;
+            pos += 2;
+            let mut pairs = Vec::with_capacity(pair_count);
+            for _ in 0..pair_count {
+                let from = get_f2_14(data, pos)?;
+                let to = get_f2_14(data, pos + 2)?;
+                pairs.push((from, to));
+                pos += 4;
+            }
+            segment_maps.push(pairs);
+        }
+        Some(Avar { segment_maps: segment_maps })
+    }
+
+    /// Remaps an already-normalized coordinate through axis `i`'s segment
+    /// map, interpolating linearly between the two bracketing pairs.
+    pub fn remap(&self, i: usize, normalized: f32) -> f32 {
+        let pairs = match self.segment_maps.get(i) {
+            Some(p) if !p.is_empty() => p,
+            _ => return normalized,
+        };
+        for w in pairs.windows(2) {
+            let (from0, to0) = w[0];
+            let (from1, to1) = w[1];
+            if normalized >= from0 && normalized <= from1 {
+                if from1 == from0 {
+                    return to0;
+                }
+                return to0 + (normalized - from0) / (from1 - from0) * (to1 - to0);
+            }
+        }
+        normalized
+    }
+}
+
+/// Normalizes a set of user-space axis coordinates (in `fvar` axis order,
+/// missing trailing axes default to their `default` value) to `[-1, 1]`,
+/// applying `avar`'s remapping if present.
+pub fn normalize_coords(fvar: &Fvar, avar: Option<&Avar>, user_coords: &[f32]) -> Vec<f32> {
+    (0..fvar.axis_count())
+        .map(|i| {
+            let user_value = user_coords.get(i).cloned().unwrap_or_else(|| {
+                fvar.axis(i).map(|(_, default, _)| default).unwrap_or(0.0)
+            });
+            let normalized = fvar.normalize(i, user_value);
+            match avar {
+                Some(avar) => avar.remap(i, normalized),
+                None => normalized,
+            }
+        })
+        .collect()
+}
+
+// `None` doubles as "all points in the glyph" (the explicit sentinel) and as
+// "truncated mid-parse" -- both mean the caller should fall back to treating
+// every point as covered rather than trust a partially-decoded list.
+fn packed_point_numbers(data: &[u8], pos: &mut usize) -> Option<Vec<u16>> {
+    let control = *data.get(*pos)?;
+    *pos += 1;
+    if control == 0 {
+        return None; // "all points in the glyph"
+    }
+    let count = if control & 0x80 != 0 {
+        let hi = (control & 0x7f) as u16;
+        let lo = *data.get(*pos)? as u16;
+        *pos += 1;
+        ((hi << 8) | lo) as usize
+    } else {
+        control as usize
+    };
+
+    let mut points = Vec::with_capacity(count.min(4096));
+    let mut last = 0u16;
+    'outer: while points.len() < count {
+        let run_control = *data.get(*pos)?;
+        *pos += 1;
+        let words = run_control & 0x80 != 0;
+        let run_len = (run_control & 0x7f) as usize + 1;
+        for _ in 0..run_len {
+            if points.len() >= count {
+                break 'outer;
+            }
+            let delta = if words {
+                let v = get_u16(data, *pos)?;
+                *pos += 2;
+                v
+            } else {
+                let v = *data.get(*pos)? as u16;
+                *pos += 1;
+                v
+            };
+            last = last.wrapping_add(delta);
+            points.push(last);
+        }
+    }
+    Some(points)
+}
+
+/// Returns `None` if the run-length-encoded deltas run past the end of
+/// `data` before producing `count` values, so the caller can drop the tuple
+/// instead of working from a short read.
+fn packed_deltas(data: &[u8], pos: &mut usize, count: usize) -> Option<Vec<i16>> {
+    let mut deltas = Vec::with_capacity(count.min(4096));
+    while deltas.len() < count {
+        let control = *data.get(*pos)?;
+        *pos += 1;
+        let run_len = (control & 0x3f) as usize + 1;
+        if control & 0x80 != 0 {
+            for _ in 0..run_len {
+                if deltas.len() >= count {
+                    break;
+                }
+                deltas.push(0);
+            }
+        } else if control & 0x40 != 0 {
+            for _ in 0..run_len {
+                if deltas.len() >= count {
+                    break;
+                }
+                deltas.push(get_i16(data, *pos)?);
+                *pos += 2;
+            }
+        } else {
+            for _ in 0..run_len {
+                if deltas.len() >= count {
+                    break;
+                }
+                deltas.push(*data.get(*pos)? as i8 as i16);
+                *pos += 1;
+            }
+        }
+    }
+    deltas.truncate(count);
+    Some(deltas)
+}
+
+const EMBEDDED_PEAK_TUPLE: u16 = 0x8000;
+const INTERMEDIATE_REGION: u16 = 0x4000;
+const PRIVATE_POINT_NUMBERS: u16 = 0x2000;
+const TUPLE_INDEX_MASK: u16 = 0x0fff;
+const SHARED_POINT_NUMBERS: u16 = 0x8000;
+const TUPLE_COUNT_MASK: u16 = 0x0fff;
+
+struct TupleHeader {
+    size: usize,
+    peak: Vec<f32>,
+    start: Vec<f32>,
+    end: Vec<f32>,
+    private_points: bool,
+}
+
+/// A parsed `gvar` table: per-glyph tuple variation stores, each a set of
+/// regions (peak + optional intermediate start/end per axis) with point
+/// deltas to blend in proportionally to how close the current instance's
+/// normalized coordinates are to that region's peak.
+pub struct Gvar<'a> {
+    data: &'a [u8],
+    axis_count: usize,
+    shared_tuples: Vec<Vec<f32>>,
+    data_array_offset: usize,
+    glyph_offsets: Vec<usize>,
+}
+
+impl<'a> Gvar<'a> {
+    pub fn parse(data: &'a [u8]) -> Option<Gvar<'a>> {
+        if data.len() < 20 {
+            return None;
+        }
+        let axis_count = get_u16(data, 4)? as usize;
+        let shared_tuple_count = get_u16(data, 6)? as usize;
+        let shared_tuples_offset = get_u32(data, 8)? as usize;
+        let glyph_count = get_u16(data, 12)? as usize;
+        let flags = get_u16(data, 14)?;
+        let data_array_offset = get_u32(data, 16)? as usize;
+
+        let mut shared_tuples = Vec::with_capacity(shared_tuple_count);
+        for i in 0..shared_tuple_count {
+            let off = shared_tuples_offset.checked_add(i.checked_mul(axis_count)?.checked_mul(2)?)?;
+            let mut tuple = Vec::with_capacity(axis_count);
+            for a in 0..axis_count {
+                tuple.push(get_f2_14(data, off + a * 2)?);
+            }
+            shared_tuples.push(tuple);
+        }
+
+        let long_offsets = flags & 1 != 0;
+        let offsets_start = 20;
+        let mut glyph_offsets = Vec::with_capacity(glyph_count + 1);
+        for i in 0..=glyph_count {
+            let off = if long_offsets {
+                get_u32(data, offsets_start + i * 4)? as usize
+            } else {
+                get_u16(data, offsets_start + i * 2)? as usize * 2
+            };
+            glyph_offsets.push(off);
+        }
+
+        Some(Gvar {
+            data: data,
+            axis_count: axis_count,
+            shared_tuples: shared_tuples,
+            data_array_offset: data_array_offset,
+            glyph_offsets: glyph_offsets,
+        })
+    }
+
+    fn glyph_data(&self, glyph_id: u16) -> Option<&'a [u8]> {
+        let i = glyph_id as usize;
+        if i + 1 >= self.glyph_offsets.len() {
+            return None;
+        }
+        let start = self.data_array_offset.checked_add(self.glyph_offsets[i])?;
+        let end = self.data_array_offset.checked_add(self.glyph_offsets[i + 1])?;
+        if start == end {
+            return None;
+        }
+        if start > end || end > self.data.len() {
+            return None;
+        }
+        Some(&self.data[start..end])
+    }
+
+    fn axis_scalar(coord: f32, start: f32, peak: f32, end: f32) -> f32 {
+        if peak == 0.0 {
+            return 1.0;
+        }
+        if coord == peak {
+            return 1.0;
+        }
+        if coord <= start || coord >= end {
+            return 0.0;
+        }
+        if coord < peak {
+            (coord - start) / (peak - start)
+        } else {
+            (end - coord) / (end - peak)
+        }
+    }
+
+    fn tuple_scalar(&self, header: &TupleHeader, norm_coords: &[f32]) -> f32 {
+        let mut scalar = 1.0;
+        for a in 0..self.axis_count {
+            let peak = header.peak[a];
+            let coord = norm_coords.get(a).cloned().unwrap_or(0.0);
+            let (start, end) = if header.start.is_empty() {
+                (peak.min(0.0), peak.max(0.0))
+            } else {
+                (header.start[a], header.end[a])
+            };
+            scalar *= Self::axis_scalar(coord, start, peak, end);
+            if scalar == 0.0 {
+                return 0.0;
+            }
+        }
+        scalar
+    }
+
+    /// Computes the summed, IUP-completed (dx, dy) delta for every point in
+    /// `points` (original `glyf` coordinates), given this glyph's tuple
+    /// variation store and the already-normalized instance coordinates.
+    /// `contour_ends` holds the index of the last point of each contour.
+    pub fn glyph_deltas(
+        &self, glyph_id: u16, points: &[(i16, i16)], contour_ends: &[usize], norm_coords: &[f32],
+    ) -> Vec<(f32, f32)> {
+        let n = points.len();
+        let mut total = vec![(0.0f32, 0.0f32); n];
+        let data = match self.glyph_data(glyph_id) {
+            Some(d) => d,
+            None => return total,
+        };
+        let tuple_count_and_flags = match get_u16(data, 0) {
+            Some(v) => v,
+            None => return total,
+        };
+        let has_shared_points = tuple_count_and_flags & SHARED_POINT_NUMBERS != 0;
+        let tuple_count = (tuple_count_and_flags & TUPLE_COUNT_MASK) as usize;
+        let serialized_offset = match get_u16(data, 2) {
+            Some(v) => v as usize,
+            None => return total,
+        };
+
+        // A truncated tuple-variation header array just stops the header
+        // list short (fewer tuples to apply) rather than panicking; the
+        // headers collected so far are still processed below.
+        let axis_tuple = |data: &[u8], pos: usize| -> Option<Vec<f32>> {
+            let mut v = Vec::with_capacity(self.axis_count);
+            for a in 0..self.axis_count {
+                v.push(get_f2_14(data, pos + a * 2)?);
+            }
+            Some(v)
+        };
+        let mut pos = 4;
+        let mut headers = Vec::with_capacity(tuple_count);
+        for _ in 0..tuple_count {
+            let header = (|| -> Option<TupleHeader> {
+                let size = get_u16(data, pos)? as usize;
+                let tuple_index = get_u16(data, pos + 2)?;
+                pos += 4;
+                let peak = if tuple_index & EMBEDDED_PEAK_TUPLE != 0 {
+                    let v = axis_tuple(data, pos)?;
+                    pos += self.axis_count * 2;
+                    v
+                } else {
+                    self.shared_tuples
+                        .get((tuple_index & TUPLE_INDEX_MASK) as usize)
+                        .cloned()
+                        .unwrap_or_else(|| vec![0.0; self.axis_count])
+                };
+                let (start, end) = if tuple_index & INTERMEDIATE_REGION != 0 {
+                    let s = axis_tuple(data, pos)?;
+                    pos += self.axis_count * 2;
+                    let e = axis_tuple(data, pos)?;
+                    pos += self.axis_count * 2;
+                    (s, e)
+                } else {
+                    (vec![], vec![])
+                };
+                Some(TupleHeader {
+                    size: size,
+                    peak: peak,
+                    start: start,
+                    end: end,
+                    private_points: tuple_index & PRIVATE_POINT_NUMBERS != 0,
+                })
+            })();
+            match header {
+                Some(h) => headers.push(h),
+                None => break,
+            }
+        }
+
+        let mut ser_pos = serialized_offset;
+        let shared_points = if has_shared_points {
+            packed_point_numbers(data, &mut ser_pos)
+        } else {
+            None
+        };
+
+        for header in &headers {
+            let tuple_start = ser_pos;
+            let scalar = self.tuple_scalar(header, norm_coords);
+            if scalar == 0.0 {
+                ser_pos = match tuple_start.checked_add(header.size) {
+                    Some(p) => p,
+                    None => break,
+                };
+                continue;
+            }
+            let point_numbers = if header.private_points {
+                packed_point_numbers(data, &mut ser_pos)
+            } else {
+                shared_points.clone()
+            };
+            let count = point_numbers.as_ref().map_or(n, |p| p.len());
+            let x_deltas = match packed_deltas(data, &mut ser_pos, count) {
+                Some(d) => d,
+                None => break,
+            };
+            let y_deltas = match packed_deltas(data, &mut ser_pos, count) {
+                Some(d) => d,
+                None => break,
+            };
+
+            let mut touched = vec![false; n];
+            let mut dx = vec![0.0f32; n];
+            let mut dy = vec![0.0f32; n];
+            match point_numbers {
+                None => {
+                    for i in 0..n.min(count) {
+                        touched[i] = true;
+                        dx[i] = x_deltas[i] as f32;
+                        dy[i] = y_deltas[i] as f32;
+                    }
+                }
+                Some(pts) => {
+                    for (k, &pt) in pts.iter().enumerate() {
+                        let pt = pt as usize;
+                        if pt < n {
+                            touched[pt] = true;
+                            dx[pt] = x_deltas[k] as f32;
+                            dy[pt] = y_deltas[k] as f32;
+                        }
+                    }
+                }
+            }
+            iup_interpolate(points, contour_ends, &mut touched, &mut dx, &mut dy);
+            for i in 0..n {
+                total[i].0 += scalar * dx[i];
+                total[i].1 += scalar * dy[i];
+            }
+
+            ser_pos = match tuple_start.checked_add(header.size) {
+                Some(p) => p,
+                None => break,
+            };
+        }
+
+        total
+    }
+}
+
+/// IUP (Inferred Unreferenced Point interpolation): fills in deltas for
+/// points a tuple left untouched, per contour, per axis.
+fn iup_interpolate(
+    points: &[(i16, i16)], contour_ends: &[usize], touched: &mut [bool], dx: &mut [f32], dy: &mut [f32],
+) {
+    let mut start = 0;
+    for &end in contour_ends {
+        if end < start || end >= points.len() {
+            continue;
+        }
+        iup_contour(points, start, end, touched, dx, true);
+        iup_contour(points, start, end, touched, dy, false);
+        start = end + 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixed_bytes(v: f32) -> [u8; 4] {
+        ((v * 65536.0) as i32).to_be_bytes()
+    }
+
+    #[test]
+    fn fvar_normalize_against_synthetic_axis() {
+        // Minimal fvar: 16-byte header, one 20-byte axis record (wght,
+        // min=0, default=400, max=900) starting right after the header.
+        let mut data = vec![0u8; 16];
+        data[4..6].copy_from_slice(&16u16.to_be_bytes()); // axesArrayOffset
+        data[8..10].copy_from_slice(&1u16.to_be_bytes()); // axisCount
+        data.extend_from_slice(b"wght");
+        data.extend_from_slice(&fixed_bytes(0.0));
+        data.extend_from_slice(&fixed_bytes(400.0));
+        data.extend_from_slice(&fixed_bytes(900.0));
+        data.extend_from_slice(&[0, 0, 0, 0]); // flags, strIndex
+
+        let fvar = Fvar::parse(&data).unwrap();
+        assert_eq!(fvar.axis_count(), 1);
+        assert_eq!(fvar.normalize(0, 400.0), 0.0);
+        assert_eq!(fvar.normalize(0, 250.0), -0.375);
+        assert_eq!(fvar.normalize(0, 700.0), 0.6);
+    }
+
+    #[test]
+    fn packed_point_numbers_single_run() {
+        // 3 points, one run of 3 byte-sized deltas: 1, 2, 1 -> 1, 3, 4.
+        let data = [0x03, 0x02, 1, 2, 1];
+        let mut pos = 0;
+        assert_eq!(packed_point_numbers(&data, &mut pos), Some(vec![1, 3, 4]));
+    }
+
+    #[test]
+    fn packed_point_numbers_all_points_sentinel() {
+        let data = [0x00];
+        let mut pos = 0;
+        assert_eq!(packed_point_numbers(&data, &mut pos), None);
+    }
+
+    #[test]
+    fn packed_deltas_byte_run() {
+        let data = [0x02, 5, 0xFBu8, 0]; // run of 3 signed bytes: 5, -5, 0
+        let mut pos = 0;
+        assert_eq!(packed_deltas(&data, &mut pos, 3), Some(vec![5, -5, 0]));
+    }
+
+    #[test]
+    fn packed_deltas_zero_run() {
+        let data = [0x82]; // zeros flag set, run length 3
+        let mut pos = 0;
+        assert_eq!(packed_deltas(&data, &mut pos, 3), Some(vec![0, 0, 0]));
+    }
+
+    #[test]
+    fn packed_point_numbers_truncated_mid_run_does_not_panic() {
+        // Declares a 2-byte-word run of 3 points but the buffer is cut off
+        // after the run-control byte.
+        let data = [0x03, 0x83];
+        let mut pos = 0;
+        assert_eq!(packed_point_numbers(&data, &mut pos), None);
+    }
+
+    #[test]
+    fn packed_deltas_truncated_mid_run_does_not_panic() {
+        // A 2-byte-word run of 3 deltas cut off after the first value.
+        let data = [0x42, 0, 5];
+        let mut pos = 0;
+        assert_eq!(packed_deltas(&data, &mut pos, 3), None);
+    }
+
+    #[test]
+    fn gvar_parse_on_truncated_data_does_not_panic() {
+        assert!(Gvar::parse(&[0u8; 19]).is_none());
+        let mut data = vec![0u8; 20];
+        data[4..6].copy_from_slice(&1u16.to_be_bytes()); // axisCount
+        data[6..8].copy_from_slice(&0xffffu16.to_be_bytes()); // sharedTupleCount (bogus)
+        assert!(Gvar::parse(&data).is_none());
+    }
+
+    #[test]
+    fn iup_interpolates_linearly_between_touched_points() {
+        // 4 collinear points on the x axis; only the endpoints are touched.
+        let points = [(0i16, 0i16), (10, 0), (20, 0), (30, 0)];
+        let contour_ends = [3usize];
+        let mut touched = [true, false, false, true];
+        let mut dx = [0.0f32, 0.0, 0.0, 9.0];
+        let mut dy = [0.0f32; 4];
+        iup_interpolate(&points, &contour_ends, &mut touched, &mut dx, &mut dy);
+        assert_eq!(dx, [0.0, 3.0, 6.0, 9.0]);
+    }
+}
+
+fn iup_contour(points: &[(i16, i16)], start: usize, end: usize, touched: &[bool], delta: &mut [f32], axis_x: bool) {
+    let touched_indices: Vec<usize> = (start..=end).filter(|&i| touched[i]).collect();
+    if touched_indices.is_empty() {
+        return;
+    }
+    if touched_indices.len() == 1 {
+        let t = touched_indices[0];
+        let d = delta[t];
+        for i in start..=end {
+            if i != t {
+                delta[i] = d;
+            }
+        }
+        return;
+    }
+    let coord = |points: &[(i16, i16)], i: usize| if axis_x { points[i].0 as f32 } else { points[i].1 as f32 };
+    let n = end - start + 1;
+    for k in 0..touched_indices.len() {
+        let cur = touched_indices[k];
+        let next = touched_indices[(k + 1) % touched_indices.len()];
+        if cur == next {
+            continue;
+        }
+        let c0 = coord(points, cur);
+        let c1 = coord(points, next);
+        let d0 = delta[cur];
+        let d1 = delta[next];
+        let mut pos = cur;
+        loop {
+            pos = start + (pos - start + 1) % n;
+            if pos == next {
+                break;
+            }
+            let cp = coord(points, pos);
+            delta[pos] = if c0 == c1 {
+                if d0 == d1 { d0 } else { 0.0 }
+            } else if cp <= c0.min(c1) {
+                if c0 < c1 { d0 } else { d1 }
+            } else if cp >= c0.max(c1) {
+                if c0 < c1 { d1 } else { d0 }
+            } else if c0 < c1 {
+                d0 + (cp - c0) / (c1 - c0) * (d1 - d0)
+            } else {
+                d1 + (cp - c1) / (c0 - c1) * (d0 - d1)
+            };
+        }
+    }
+}
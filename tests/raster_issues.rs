@@ -10,3 +10,33 @@ fn draw_line_index_panic() {
     r.draw_line(&Point::new(3.7399998, 13.799999), &Point::new(3.7399998, 0.0));
     r.draw_line(&Point::new(3.7399998, 0.0), &Point::new(0.0, 0.10000038));
 }
+
+/// A segment whose endpoints are entirely above and below the buffer must be
+/// clipped to the `y=0`/`y=h` scanlines rather than panic.
+#[test]
+fn draw_line_y_far_out_of_range() {
+    let mut r = Raster::new(8, 8);
+    r.draw_line(&Point::new(4.0, -1000.0), &Point::new(4.0, 1000.0));
+    r.get_bitmap();
+}
+
+/// A segment entirely to the left of the buffer must still contribute its
+/// full winding delta; one entirely to the right must contribute nothing.
+#[test]
+fn draw_line_x_far_out_of_range() {
+    let mut r = Raster::new(8, 8);
+    r.draw_line(&Point::new(-1000.0, 0.0), &Point::new(-1000.0, 8.0));
+    r.draw_line(&Point::new(1000.0, 0.0), &Point::new(1000.0, 8.0));
+    r.get_bitmap();
+}
+
+/// A fully-covered glyph region must come out fully opaque (255) on every
+/// subpixel channel, not ~1/3 bright from a mis-normalized LCD filter.
+#[test]
+fn get_bitmap_lcd_full_coverage_is_opaque() {
+    let mut r = Raster::try_new_subpixel(4, 1).unwrap();
+    r.draw_line(&Point::new(0.0, 0.0), &Point::new(0.0, 1.0));
+    r.draw_line(&Point::new(4.0, 1.0), &Point::new(4.0, 0.0));
+    let bitmap = r.get_bitmap_lcd(false);
+    assert_eq!(bitmap, vec![255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255]);
+}